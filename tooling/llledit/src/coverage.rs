@@ -0,0 +1,209 @@
+//! Finds the inflected forms and flags that are missing from an lll, so that
+//! curators know what to add next.
+
+use super::*;
+use extension::Relation;
+use std::collections::HashSet;
+
+/// The flags of the forms we look for.
+const FORM_FLAGS: [Flags; 2] = [PLURAL_NOUN, THIRD_PERSON_SINGULAR_VERB];
+
+pub struct Report<'lll> {
+    lll: &'lll [LLRef<'lll>],
+    /// Singular nouns with no plural, along with a guess at the plural.
+    missing_plurals: Vec<(usize, String)>,
+    /// First person verbs with no third person form, along with a guess
+    /// at that form.
+    missing_third_person_singulars: Vec<(usize, String)>,
+    /// Verbs that are neither intransitive nor transitive.
+    missing_transitivity: Vec<usize>,
+}
+
+/// Whether `ll` has its `relation` in the lll, either linked, or just
+/// with the guessed form and the right flag. `forms` holds the
+/// comparison key of each lexeme, under each of its `FORM_FLAGS`.
+fn has_form(
+    ll: &LLRef<'_>,
+    relation: Relation,
+    guess: &str,
+    flag: Flags,
+    forms: &HashSet<(Flags, String)>,
+    canonical: canonical::Options,
+) -> bool {
+    let linked = ll.parsed_extensions().any(|extension| matches!(
+        extension,
+        Extension::Related{ relation: r, .. } if r == relation
+    ));
+
+    linked || forms.contains(&(flag, canonical.comparison_key(guess)))
+}
+
+pub fn report<'lll>(lll: &'lll [LLRef<'lll>], canonical: canonical::Options) -> Report<'lll> {
+    let mut report = Report {
+        lll,
+        missing_plurals: Vec::new(),
+        missing_third_person_singulars: Vec::new(),
+        missing_transitivity: Vec::new(),
+    };
+
+    let mut forms = HashSet::new();
+    for ll in lll {
+        for flag in FORM_FLAGS {
+            if ll.flags & flag != 0 {
+                forms.insert((flag, canonical.comparison_key(ll.lexeme)));
+            }
+        }
+    }
+
+    for (i, ll) in lll.iter().enumerate() {
+        let lexeme = ll.lexeme;
+
+        if ll.flags & SINGULAR_NOUN != 0 {
+            let guess = inflection::plural(lexeme);
+            if !has_form(ll, Relation::Plural, &guess, PLURAL_NOUN, &forms, canonical) {
+                report.missing_plurals.push((i, guess));
+            }
+        }
+
+        if ll.flags & FIRST_PERSON_SINGULAR_VERB != 0 {
+            let guess = inflection::third_person_singular(lexeme);
+            if !has_form(
+                ll,
+                Relation::ThirdPersonSingular,
+                &guess,
+                THIRD_PERSON_SINGULAR_VERB,
+                &forms,
+                canonical,
+            ) {
+                report.missing_third_person_singulars.push((i, guess));
+            }
+        }
+
+        let is_verb = ll.flags & (FIRST_PERSON_SINGULAR_VERB | THIRD_PERSON_SINGULAR_VERB) != 0;
+        if is_verb && ll.flags & (INTRANSITIVE_VERB | TRANSITIVE_VERB) == 0 {
+            report.missing_transitivity.push(i);
+        }
+    }
+
+    report
+}
+
+/// Each gap is the index of a lexeme, and maybe a guess at what to add.
+fn write_section<'gap>(
+    f: &mut std::fmt::Formatter<'_>,
+    lll: &[LLRef<'_>],
+    title: &str,
+    gaps: impl ExactSizeIterator<Item = (usize, Option<&'gap str>)>,
+) -> std::fmt::Result {
+    writeln!(f, "{title}: {}", gaps.len())?;
+    for (index, guess) in gaps {
+        write!(f, "  @{index} \"{}\"", lll[index].lexeme)?;
+        if let Some(guess) = guess {
+            write!(f, ", maybe add \"{guess}\"")?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn with_guess((i, guess): &(usize, String)) -> (usize, Option<&str>) {
+            (*i, Some(guess))
+        }
+
+        write_section(
+            f,
+            self.lll,
+            "Singular nouns without a plural",
+            self.missing_plurals.iter().map(with_guess),
+        )?;
+        writeln!(f)?;
+        write_section(
+            f,
+            self.lll,
+            "First person verbs without a third person form",
+            self.missing_third_person_singulars.iter().map(with_guess),
+        )?;
+        writeln!(f)?;
+        write_section(
+            f,
+            self.lll,
+            "Verbs that are neither INTRANSITIVE_VERB nor TRANSITIVE_VERB",
+            self.missing_transitivity.iter().map(|&i| (i, None)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_report(lll: &[LL], canonical: canonical::Options, check: impl FnOnce(Report<'_>)) {
+        let bytes = encode_lll(lll);
+        let lll = parse_lll_refs(&bytes).unwrap();
+        check(report(&lll, canonical));
+    }
+
+    fn guess(index: usize, guess: &str) -> (usize, String) {
+        (index, guess.to_string())
+    }
+
+    #[test]
+    fn reports_each_kind_of_gap() {
+        let lll = [
+            ll("cow", SINGULAR_NOUN),
+            ll("hat", SINGULAR_NOUN),
+            ll("hats", PLURAL_NOUN),
+            ll("moo", FIRST_PERSON_SINGULAR_VERB | INTRANSITIVE_VERB),
+            ll("chew", FIRST_PERSON_SINGULAR_VERB),
+            ll("chews", THIRD_PERSON_SINGULAR_VERB | TRANSITIVE_VERB),
+        ];
+
+        with_report(&lll, canonical::Options::default(), |report| {
+            assert_eq!(report.missing_plurals, [guess(0, "cows")]);
+            assert_eq!(report.missing_third_person_singulars, [guess(3, "moos")]);
+            assert_eq!(report.missing_transitivity, [4]);
+        });
+    }
+
+    #[test]
+    fn a_linked_form_counts_as_covered() {
+        let mut cow = ll("cow", SINGULAR_NOUN);
+        cow.extensions.push(Extension::Related{ relation: Relation::Plural, index: 1 });
+        let mut moo = ll("moo", FIRST_PERSON_SINGULAR_VERB | INTRANSITIVE_VERB);
+        moo.extensions.push(Extension::Related{
+            relation: Relation::ThirdPersonSingular,
+            index: 3,
+        });
+        let lll = [cow, ll("kine", PLURAL_NOUN), moo, ll("mooes", THIRD_PERSON_SINGULAR_VERB)];
+
+        with_report(&lll, canonical::Options::default(), |report| {
+            assert!(report.missing_plurals.is_empty());
+            assert!(report.missing_third_person_singulars.is_empty());
+        });
+    }
+
+    #[test]
+    fn the_guessed_form_only_counts_with_the_right_flag() {
+        let lll = [ll("cow", SINGULAR_NOUN), ll("cows", THIRD_PERSON_SINGULAR_VERB)];
+
+        with_report(&lll, canonical::Options::default(), |report| {
+            assert_eq!(report.missing_plurals, [guess(0, "cows")]);
+        });
+    }
+
+    #[test]
+    fn guessed_forms_are_compared_like_lexemes() {
+        let lll = [ll("Cow", SINGULAR_NOUN), ll("cows", PLURAL_NOUN)];
+
+        with_report(&lll, canonical::Options::default(), |report| {
+            assert_eq!(report.missing_plurals, [guess(0, "Cows")]);
+        });
+
+        let fold_case = canonical::Options{ fold_case: true, ..Default::default() };
+        with_report(&lll, fold_case, |report| {
+            assert!(report.missing_plurals.is_empty());
+        });
+    }
+}
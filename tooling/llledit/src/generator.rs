@@ -0,0 +1,673 @@
+//! Fills in templates with lexemes from an lll.
+
+use super::*;
+use inflection::Article;
+use std::borrow::Cow;
+use template::{Category, Part, Slot, Template};
+
+/// A small, fast, non-cryptographic random number generator. Snowclones
+/// do not need anything better, and this way, the same seed always
+/// gives the same snowclones.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at 0, and small seeds take a while to get
+        // going, so mix the seed first. (SplitMix64's finaliser.)
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Self(if z == 0 { 1 } else { z })
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(nanos ^ u64::from(std::process::id()))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // xorshift64*
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a number from 0 up to, but not including, `n`, which must
+    /// not be 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        self.below_u64(n as u64) as usize
+    }
+
+    fn below_u64(&mut self, n: u64) -> u64 {
+        debug_assert!(n > 0);
+        // The bias from using `%` is far too small to notice here.
+        self.next_u64() % n
+    }
+
+    /// Picks one of `lls`, which must not be empty, with each lexeme's
+    /// chances in proportion to its weight.
+    pub fn pick<'lll>(&mut self, lls: &[&'lll LLRef<'lll>]) -> &'lll LLRef<'lll> {
+        let total: u64 = lls.iter().map(|ll| weight(ll)).sum();
+        if total == 0 {
+            // Everything was toned down all the way, but something has
+            // to fill the slot.
+            return lls[self.below(lls.len())]
+        }
+
+        let mut target = self.below_u64(total);
+        for ll in lls {
+            let weight = weight(ll);
+            if target < weight {
+                return ll
+            }
+            target -= weight;
+        }
+        unreachable!("target is below the total of the weights")
+    }
+}
+
+/// How often the lexeme should come up, relative to the others. This is
+/// its FREQUENCY, or 1 if it does not have one, as LLL.md says.
+fn weight(ll: &LLRef<'_>) -> u64 {
+    ll.parsed_extensions()
+        .filter_map(|extension| match extension {
+            Extension::Frequency(frequency) => Some(u64::from(frequency)),
+            _ => None,
+        })
+        .last()
+        .unwrap_or(1)
+}
+
+fn candidates<'lll>(lll: &'lll [LLRef<'lll>], category: Category) -> Vec<&'lll LLRef<'lll>> {
+    lll.iter().filter(|ll| category.matches(ll.flags)).collect()
+}
+
+/// The article the lexeme asks for, if it asks for one.
+fn article_override(ll: &LLRef<'_>) -> Option<Article> {
+    ll.parsed_extensions()
+        .filter_map(|extension| match extension {
+            Extension::Article(article) => Some(article),
+            _ => None,
+        })
+        .last()
+}
+
+enum Piece<'lll> {
+    /// `lexeme` is what the text came from, if it came from a lexeme.
+    Text{ text: Cow<'lll, str>, lexeme: Option<&'lll LLRef<'lll>> },
+    Article,
+}
+
+fn contains(lls: &[&LLRef<'_>], ll: &LLRef<'_>) -> bool {
+    lls.iter().any(|other| std::ptr::eq(*other, ll))
+}
+
+/// Picks lexemes to fill `slot`, other than the ones in `exclude`, and
+/// preferably other than the ones in `avoid`. If there are `orders`, only
+/// adjectives from those adjective order categories are picked. The
+/// lexemes picked are all different, and in adjective order.
+fn fill<'lll>(
+    lll: &'lll [LLRef<'lll>],
+    slot: &Slot,
+    exclude: &[&LLRef<'_>],
+    avoid: &[&LLRef<'_>],
+    orders: Option<&[AdjectiveOrderCategory]>,
+    rng: &mut Rng,
+) -> Result<Vec<&'lll LLRef<'lll>>, ErrMsg> {
+    let mut candidates = candidates(lll, slot.category);
+    candidates.retain(|ll| !contains(exclude, ll));
+    if let Some(orders) = orders {
+        candidates.retain(|ll| orders.contains(&flag_display::adjective_order_category(ll.flags)));
+    }
+
+    if !avoid.is_empty() {
+        let mut fresh = candidates.clone();
+        fresh.retain(|ll| !contains(avoid, ll));
+        // Lexemes toned down to 0 are only for when nothing else will
+        // do, and a recent lexeme will.
+        if fresh.iter().any(|ll| weight(ll) > 0) {
+            if let Ok(lls) = fill_from(fresh, slot, rng) {
+                return Ok(lls)
+            }
+        }
+    }
+
+    fill_from(candidates, slot, rng)
+}
+
+fn fill_from<'lll>(
+    mut candidates: Vec<&'lll LLRef<'lll>>,
+    slot: &Slot,
+    rng: &mut Rng,
+) -> Result<Vec<&'lll LLRef<'lll>>, ErrMsg> {
+    let order = |ll: &LLRef<'_>| flag_display::adjective_order_category(ll.flags);
+
+    let mut lls = Vec::with_capacity(slot.count);
+    for _ in 0..slot.count {
+        if candidates.is_empty() {
+            return Err("The lll has nothing to fill one of the template's slots")
+        }
+
+        let ll = if slot.coordinate || slot.count == 1 {
+            rng.pick(&candidates)
+        } else {
+            // Pick the category first, like index.html does, so that
+            // categories with lots of adjectives do not crowd out the
+            // rest. Categories with only adjectives toned down to 0 are
+            // left out, unless nothing else is left.
+            let weighted = candidates.iter().any(|ll| weight(ll) > 0);
+            let mut blocks: Vec<AdjectiveOrderCategory> = candidates.iter()
+                .filter(|ll| !weighted || weight(ll) > 0)
+                .map(|ll| order(ll))
+                .collect();
+            blocks.sort_unstable();
+            blocks.dedup();
+            let block = blocks[rng.below(blocks.len())];
+
+            let in_block: Vec<&LLRef<'_>> = candidates.iter()
+                .copied()
+                .filter(|ll| order(ll) == block)
+                .collect();
+            rng.pick(&in_block)
+        };
+
+        if slot.coordinate {
+            candidates.retain(|other| !std::ptr::eq(*other, ll));
+        } else {
+            candidates.retain(|other| order(other) != order(ll));
+        }
+        lls.push(ll);
+    }
+
+    lls.sort_by_key(|ll| order(ll));
+    Ok(lls)
+}
+
+/// The lexemes generated recently, for each slot category, so that they
+/// can be avoided until enough of the others have come up. Within each
+/// category, this works like a shuffle bag, if the fraction is 1.
+/// Lexemes toned down to 0 do not count, unless every lexeme in the
+/// category is.
+pub struct History<'lll> {
+    /// How much of a category's candidates have to come up before a
+    /// lexeme can come up again, from 0, for no history, to 1.
+    fraction: f64,
+    recent: Vec<(Category, Vec<&'lll LLRef<'lll>>)>,
+}
+
+/// The fraction `History` uses when not told otherwise.
+pub const DEFAULT_AVOID_REPEATS: f64 = 0.5;
+
+impl<'lll> History<'lll> {
+    pub fn new(fraction: f64) -> Self {
+        Self {
+            fraction: fraction.clamp(0.0, 1.0),
+            recent: Vec::new(),
+        }
+    }
+
+    fn recent(&self, category: Category) -> &[&'lll LLRef<'lll>] {
+        self.recent.iter()
+            .find(|(c, _)| *c == category)
+            .map(|(_, recent)| recent.as_slice())
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, lll: &[LLRef<'_>], category: Category, lls: &[&'lll LLRef<'lll>]) {
+        let weighted = lll.iter()
+            .filter(|ll| category.matches(ll.flags) && weight(ll) > 0)
+            .count();
+        let pool = if weighted > 0 {
+            weighted
+        } else {
+            template::candidate_count(lll, category)
+        };
+        let window = ((pool as f64 * self.fraction).round() as usize).min(pool);
+        if window == 0 {
+            return
+        }
+
+        let recent = match self.recent.iter().position(|(c, _)| *c == category) {
+            Some(i) => &mut self.recent[i].1,
+            None => {
+                self.recent.push((category, Vec::with_capacity(window)));
+                &mut self.recent.last_mut().expect("just pushed").1
+            }
+        };
+
+        recent.extend(lls.iter().filter(|ll| weighted == 0 || weight(ll) > 0));
+        if recent.len() >= pool {
+            // Everything has come up, so start a new bag.
+            recent.clear();
+        } else if recent.len() > window {
+            recent.drain(..recent.len() - window);
+        }
+    }
+}
+
+/// How to capitalise generated snowclones. Neither ever makes a letter
+/// lowercase, and lexemes marked to keep their case are left as they
+/// are, so proper nouns, like "Martian", stay that way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Casing {
+    /// Capitalise the first word of each sentence.
+    #[default]
+    Sentence,
+    /// Also capitalise every other word, except short ones like "the",
+    /// as in a headline.
+    Title,
+}
+
+/// Words that stay lowercase in title case, unless they start a
+/// sentence.
+const TITLE_CASE_MINOR_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "but", "by", "for", "in", "nor", "of", "on", "or",
+    "the", "to",
+];
+
+fn keeps_case(ll: &LLRef<'_>) -> bool {
+    ll.parsed_extensions().any(|extension| matches!(extension, Extension::KeepCase))
+}
+
+/// Joins `spans` together, capitalising them according to `casing`,
+/// except for the spans marked to keep their case.
+fn apply_casing<'span>(
+    spans: impl IntoIterator<Item = (&'span str, bool)>,
+    casing: Casing,
+) -> String {
+    let mut output = String::new();
+
+    let mut sentence_start = true;
+    // Whether the last character was part of a word, so the next one
+    // does not start one.
+    let mut in_word = false;
+
+    for (text, keep_case) in spans {
+        for (i, c) in text.char_indices() {
+            let is_word_char = c.is_alphanumeric() || (in_word && c == '\'');
+
+            let word_start = is_word_char && !in_word;
+            let capitalise = word_start && !keep_case && (
+                sentence_start
+                || casing == Casing::Title && {
+                    let word: String = text[i..].chars()
+                        .take_while(|c| c.is_alphanumeric() || *c == '\'')
+                        .flat_map(char::to_lowercase)
+                        .collect();
+                    !TITLE_CASE_MINOR_WORDS.contains(&word.as_str())
+                }
+            );
+
+            if capitalise {
+                output.extend(c.to_uppercase());
+            } else {
+                output.push(c);
+            }
+
+            if is_word_char {
+                sentence_start = false;
+            } else if matches!(c, '.' | '!' | '?') {
+                sentence_start = true;
+            }
+            in_word = is_word_char;
+        }
+    }
+
+    output
+}
+
+/// Fills in `parts` with lexemes from `lll`, following the slots'
+/// constraints, and avoiding the lexemes in `history` where possible.
+pub fn fill_parts<'lll>(
+    lll: &'lll [LLRef<'lll>],
+    parts: &[Part],
+    casing: Casing,
+    history: &mut History<'lll>,
+    rng: &mut Rng,
+) -> Result<String, ErrMsg> {
+    // What was picked for each named slot, and each distinct group.
+    let mut named: Vec<(&str, Vec<&LLRef<'_>>)> = Vec::new();
+    let mut used: Vec<(&str, Vec<&LLRef<'_>>)> = Vec::new();
+    // The adjective order category of each kin group.
+    let mut kin_orders: Vec<(&str, AdjectiveOrderCategory)> = Vec::new();
+    // What was picked for each slot, to go into the history once the
+    // whole template is filled in.
+    let mut picked: Vec<(Category, Vec<&LLRef<'_>>)> = Vec::new();
+
+    let mut pieces = Vec::with_capacity(parts.len());
+    for part in parts {
+        let slot = match part {
+            Part::Text(text) => {
+                pieces.push(Piece::Text{ text: Cow::Borrowed(text), lexeme: None });
+                continue
+            }
+            Part::Article => {
+                pieces.push(Piece::Article);
+                continue
+            }
+            Part::Slot(slot) => slot,
+        };
+
+        let already = slot.same.as_deref().and_then(|same| {
+            named.iter().find(|(name, _)| *name == same).map(|(_, lls)| lls.clone())
+        });
+        let lls = match already {
+            Some(lls) => lls,
+            None => {
+                let group = slot.distinct.as_deref();
+                let exclude = group
+                    .and_then(|group| used.iter().find(|(name, _)| *name == group))
+                    .map(|(_, lls)| lls.as_slice())
+                    .unwrap_or_default();
+                let orders = slot.kin.as_deref().map(|kin| {
+                    match kin_orders.iter().find(|(name, _)| *name == kin) {
+                        Some(&(_, order)) => vec![order],
+                        None => template::kin_order_categories(lll, parts, kin),
+                    }
+                });
+                let avoid = history.recent(slot.category);
+                let lls = fill(lll, slot, exclude, avoid, orders.as_deref(), rng)
+                    .map_err(|err| if group.is_some() {
+                        "The lll does not have enough different lexemes to fill the template"
+                    } else {
+                        err
+                    })?;
+
+                if let Some(group) = group {
+                    match used.iter_mut().find(|(name, _)| *name == group) {
+                        Some((_, group_lls)) => group_lls.extend(&lls),
+                        None => used.push((group, lls.clone())),
+                    }
+                }
+                if let Some(same) = slot.same.as_deref() {
+                    named.push((same, lls.clone()));
+                }
+                if let (Some(kin), Some(ll)) = (slot.kin.as_deref(), lls.first()) {
+                    if !kin_orders.iter().any(|(name, _)| *name == kin) {
+                        kin_orders.push((kin, flag_display::adjective_order_category(ll.flags)));
+                    }
+                }
+                picked.push((slot.category, lls.clone()));
+                lls
+            }
+        };
+
+        let mut previous: Option<&LLRef<'_>> = None;
+        for ll in lls {
+            if let Some(previous) = previous {
+                let coordinate = flag_display::adjective_order_category(previous.flags)
+                    == flag_display::adjective_order_category(ll.flags);
+                let separator = if coordinate { ", " } else { " " };
+                pieces.push(Piece::Text{ text: Cow::Borrowed(separator), lexeme: None });
+            }
+            previous = Some(ll);
+            pieces.push(Piece::Text{ text: Cow::Borrowed(ll.lexeme), lexeme: Some(ll) });
+        }
+    }
+
+    let spans = pieces.iter().enumerate().map(|(i, piece)| match piece {
+        Piece::Text{ text, lexeme } => (&**text, lexeme.is_some_and(keeps_case)),
+        Piece::Article => {
+            // Articles depend on what follows them, so they are
+            // decided after everything else is filled in.
+            let following = pieces[i + 1..].iter().find_map(|piece| match piece {
+                Piece::Text{ text, lexeme } if !text.trim().is_empty() => Some((text, *lexeme)),
+                _ => None,
+            });
+            let article = match following {
+                Some((text, lexeme)) => lexeme
+                    .and_then(article_override)
+                    .unwrap_or_else(|| inflection::article(text)),
+                None => Article::A,
+            };
+            (article.as_str(), false)
+        }
+    });
+
+    let output = apply_casing(spans, casing);
+
+    for (category, lls) in picked {
+        history.record(lll, category, &lls);
+    }
+
+    Ok(output)
+}
+
+/// Fills in one of the variants of `template` that `lll` has what it
+/// needs for.
+pub fn generate<'lll>(
+    lll: &'lll [LLRef<'lll>],
+    template: &Template,
+    casing: Casing,
+    history: &mut History<'lll>,
+    rng: &mut Rng,
+) -> Result<String, ErrMsg> {
+    let usable: Vec<&Vec<Part>> = template.variants.iter()
+        .filter(|parts| template::problems(lll, parts).is_empty())
+        .collect();
+
+    if usable.is_empty() {
+        return Err("The lll does not have what this template needs. See `llledit templates`.")
+    }
+
+    let parts = usable[rng.below(usable.len())];
+    // Picking slots one at a time can paint us into a corner when
+    // categories overlap, like taking the only singular noun for a noun
+    // slot, before a singular-noun slot in the same distinct group. So
+    // try again a few times before giving up.
+    let mut result = fill_parts(lll, parts, casing, history, rng);
+    for _ in 0..FILL_ATTEMPTS {
+        if result.is_ok() {
+            break
+        }
+        result = fill_parts(lll, parts, casing, history, rng);
+    }
+    result
+}
+
+/// How many more times `generate` tries to fill in a template, after the
+/// first time does not work out.
+const FILL_ATTEMPTS: usize = 100;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_frequency(mut ll: LL, frequency: u32) -> LL {
+        ll.extensions.push(Extension::Frequency(frequency));
+        ll
+    }
+
+    /// Fills in `source` `count` times, with a fixed seed.
+    fn fill_in(lll: &[LL], source: &str, avoid_repeats: f64, count: usize) -> Vec<String> {
+        let bytes = encode_lll(lll);
+        let lll = parse_lll_refs(&bytes).unwrap();
+        let parts = template::parse(source).unwrap();
+        let mut history = History::new(avoid_repeats);
+        let mut rng = Rng::new(1);
+
+        (0..count)
+            .map(|_| fill_parts(&lll, &parts, Casing::Sentence, &mut history, &mut rng).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn articles_follow_the_next_lexeme() {
+        let mut herb = ll("herb", SINGULAR_NOUN);
+        herb.extensions.push(Extension::Article(Article::An));
+
+        let source = "{a} {singular-noun}";
+        assert_eq!(fill_in(&[ll("hour", SINGULAR_NOUN)], source, 0.0, 1), ["An hour"]);
+        assert_eq!(fill_in(&[ll("hat", SINGULAR_NOUN)], source, 0.0, 1), ["A hat"]);
+        assert_eq!(fill_in(&[herb], source, 0.0, 1), ["An herb"]);
+    }
+
+    fn cased(text: &str, casing: Casing) -> String {
+        apply_casing([(text, false)], casing)
+    }
+
+    #[test]
+    fn sentence_case_capitalises_the_start_of_each_sentence() {
+        assert_eq!(
+            cased("many hands. so work! or not? fine", Casing::Sentence),
+            "Many hands. So work! Or not? Fine",
+        );
+        assert_eq!(cased("the end of the world", Casing::Sentence), "The end of the world");
+    }
+
+    #[test]
+    fn title_case_leaves_minor_words_lowercase() {
+        assert_eq!(
+            cased("the cow and the moon of the night", Casing::Title),
+            "The Cow and the Moon of the Night",
+        );
+        assert_eq!(cased("you may not need a cow", Casing::Title), "You May Not Need a Cow");
+    }
+
+    #[test]
+    fn apostrophes_do_not_start_words() {
+        assert_eq!(cased("you can't mind your i's", Casing::Title), "You Can't Mind Your I's");
+        assert_eq!(cased("'tis the cow's", Casing::Sentence), "'Tis the cow's");
+    }
+
+    #[test]
+    fn lexemes_that_keep_their_case_are_left_alone() {
+        let spans = [("iPhone", true), (" considered harmful. ", false), ("iPhone", true)];
+        assert_eq!(apply_casing(spans, Casing::Sentence), "iPhone considered harmful. iPhone");
+        assert_eq!(apply_casing(spans, Casing::Title), "iPhone Considered Harmful. iPhone");
+
+        let mut iphone = ll("iPhone", SINGULAR_NOUN);
+        iphone.extensions.push(Extension::KeepCase);
+        assert_eq!(
+            fill_in(&[iphone], "{noun} considered harmful.", 0.0, 1),
+            ["iPhone considered harmful."],
+        );
+    }
+
+    #[test]
+    fn slots_in_a_distinct_group_get_different_lexemes() {
+        let lll = [ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN), ll("egg", SINGULAR_NOUN)];
+
+        for output in fill_in(&lll, "{noun!n} {noun!n} {noun!n}", 0.0, 50) {
+            let mut words: Vec<&str> = output.split(' ').collect();
+            words.sort_unstable();
+            words.dedup();
+            assert_eq!(words.len(), 3, "{output}");
+        }
+    }
+
+    #[test]
+    fn slots_in_a_kin_group_get_adjectives_from_the_same_category() {
+        let lll = [
+            adjective("red", 10),
+            adjective("blue", 10),
+            adjective("old", 9),
+            adjective("big", 6),
+            adjective("small", 6),
+        ];
+
+        let outputs = fill_in(&lll, "{adjective!a~k} is the new {adjective!a~k}.", 0.0, 50);
+        for output in &outputs {
+            assert!(
+                [
+                    "Red is the new blue.",
+                    "Blue is the new red.",
+                    "Big is the new small.",
+                    "Small is the new big.",
+                ].contains(&output.as_str()),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn slots_with_the_same_name_get_the_same_lexeme() {
+        let lll = [ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN), ll("egg", SINGULAR_NOUN)];
+
+        for output in fill_in(&lll, "{noun=x} {noun=x}", 0.0, 50) {
+            let (first, second) = output.split_once(' ').unwrap();
+            assert_eq!(first.to_lowercase(), second, "{output}");
+        }
+    }
+
+    #[test]
+    fn pick_never_picks_a_weight_of_0_over_a_positive_one() {
+        let bytes = encode_lll(&[
+            with_frequency(ll("apple", SINGULAR_NOUN), 0),
+            with_frequency(ll("zed", SINGULAR_NOUN), 5),
+        ]);
+        let lll = parse_lll_refs(&bytes).unwrap();
+        let lls: Vec<&LLRef<'_>> = lll.iter().collect();
+        let mut rng = Rng::new(1);
+
+        for _ in 0..100 {
+            assert_eq!(rng.pick(&lls).lexeme, "zed");
+        }
+    }
+
+    #[test]
+    fn pick_picks_from_all_of_them_when_every_weight_is_0() {
+        let bytes = encode_lll(&[
+            with_frequency(ll("apple", SINGULAR_NOUN), 0),
+            with_frequency(ll("zed", SINGULAR_NOUN), 0),
+        ]);
+        let lll = parse_lll_refs(&bytes).unwrap();
+        let lls: Vec<&LLRef<'_>> = lll.iter().collect();
+        let mut rng = Rng::new(1);
+
+        let picks: Vec<&str> = (0..100).map(|_| rng.pick(&lls).lexeme).collect();
+        assert!(picks.contains(&"apple"));
+        assert!(picks.contains(&"zed"));
+    }
+
+    #[test]
+    fn adjectives_toned_down_to_0_are_left_out_while_others_remain() {
+        let lll = [
+            with_frequency(adjective("big", 6), 0),
+            adjective("old", 9),
+            adjective("red", 10),
+        ];
+
+        for output in fill_in(&lll, "{adjective*2}", 0.0, 50) {
+            assert_eq!(output, "Old red");
+        }
+    }
+
+    #[test]
+    fn avoiding_repeats_does_not_bring_up_lexemes_toned_down_to_0() {
+        let lll = [
+            ll("apple", SINGULAR_NOUN),
+            with_frequency(ll("zed", SINGULAR_NOUN), 0),
+        ];
+
+        for avoid_repeats in [0.0, DEFAULT_AVOID_REPEATS, 1.0] {
+            for output in fill_in(&lll, "{singular-noun}", avoid_repeats, 20) {
+                assert_eq!(output, "Apple");
+            }
+        }
+    }
+
+    #[test]
+    fn avoiding_repeats_goes_through_every_lexeme_with_a_fraction_of_1() {
+        let lll = [
+            with_frequency(ll("apple", SINGULAR_NOUN), 5),
+            ll("pear", SINGULAR_NOUN),
+            ll("plum", SINGULAR_NOUN),
+            with_frequency(ll("zed", SINGULAR_NOUN), 0),
+        ];
+
+        let outputs = fill_in(&lll, "{singular-noun}", 1.0, 9);
+        for bag in outputs.chunks(3) {
+            let mut bag = bag.to_vec();
+            bag.sort();
+            assert_eq!(bag, ["Apple", "Pear", "Plum"]);
+        }
+    }
+}
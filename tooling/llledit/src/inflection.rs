@@ -0,0 +1,188 @@
+//! Guesses at how English words inflect, so the editor can suggest the other
+//! forms of a word as it is added. These are only suggestions, so following
+//! the regular rules, plus a list of common exceptions, is good enough.
+
+/// Nouns whose plurals do not follow the regular rules.
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+    ("calf", "calves"),
+    ("child", "children"),
+    ("deer", "deer"),
+    ("echo", "echoes"),
+    ("fish", "fish"),
+    ("foot", "feet"),
+    ("fungus", "fungi"),
+    ("goose", "geese"),
+    ("half", "halves"),
+    ("hero", "heroes"),
+    ("knife", "knives"),
+    ("leaf", "leaves"),
+    ("life", "lives"),
+    ("loaf", "loaves"),
+    ("louse", "lice"),
+    ("man", "men"),
+    ("moose", "moose"),
+    ("mouse", "mice"),
+    ("ox", "oxen"),
+    ("person", "people"),
+    ("potato", "potatoes"),
+    ("series", "series"),
+    ("sheep", "sheep"),
+    ("shelf", "shelves"),
+    ("species", "species"),
+    ("thief", "thieves"),
+    ("tomato", "tomatoes"),
+    ("tooth", "teeth"),
+    ("wife", "wives"),
+    ("wolf", "wolves"),
+    ("woman", "women"),
+];
+
+/// Verbs whose third person singular forms do not follow the regular
+/// rules.
+const IRREGULAR_THIRD_PERSON_SINGULARS: &[(&str, &str)] = &[
+    ("am", "is"),
+    ("be", "is"),
+    ("do", "does"),
+    ("go", "goes"),
+    ("have", "has"),
+];
+
+/// The regular rule for both plurals and third person singulars.
+fn add_s(word: &str) -> String {
+    let ends_with_consonant_y = word.strip_suffix('y')
+        .and_then(|rest| rest.chars().last())
+        .is_some_and(|c| c.is_ascii_alphabetic() && !"aeiouAEIOU".contains(c));
+
+    if ends_with_consonant_y {
+        format!("{}ies", &word[..word.len() - 1])
+    } else if ["s", "x", "z", "ch", "sh"].iter().any(|suffix| word.ends_with(suffix)) {
+        format!("{word}es")
+    } else {
+        format!("{word}s")
+    }
+}
+
+fn inflect(word: &str, irregulars: &[(&str, &str)]) -> String {
+    let lower = word.to_lowercase();
+    match irregulars.iter().find(|(base, _)| *base == lower) {
+        Some((_, inflected)) if lower == word => inflected.to_string(),
+        // Keep the capital on words like names, which are more likely to
+        // be capitalised than shouted.
+        Some((_, inflected)) => {
+            let mut chars = inflected.chars();
+            chars.next()
+                .map(|first| first.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        None => add_s(word),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Article {
+    A,
+    An,
+}
+
+impl Article {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::An => "an",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "a" => Some(Self::A),
+            "an" => Some(Self::An),
+            _ => None,
+        }
+    }
+}
+
+/// Words starting with these take "an", despite starting with a
+/// consonant, because the "h" is silent.
+const AN_PREFIXES: &[&str] = &["heir", "honest", "honor", "honour", "hour"];
+
+/// Words starting with these take "a", despite starting with a vowel,
+/// because they start with a "y" or "w" sound.
+const A_PREFIXES: &[&str] = &[
+    "eu", "ewe", "ubiq", "uku", "uni", "ure", "uri", "uro", "usa", "use", "usu", "uten",
+    "uter", "uti", "uto",
+];
+
+/// Like `A_PREFIXES`, but only for the whole word, since, for example,
+/// "onerous" takes "an".
+const A_WORDS: &[&str] = &["one", "once", "u"];
+
+/// The indefinite article that goes before `phrase`, going by how its
+/// first word is usually pronounced.
+pub fn article(phrase: &str) -> Article {
+    let word: String = phrase.trim_start()
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '\'')
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    if AN_PREFIXES.iter().any(|prefix| word.starts_with(prefix)) {
+        Article::An
+    } else if A_PREFIXES.iter().any(|prefix| word.starts_with(prefix))
+    || A_WORDS.contains(&word.as_str()) {
+        Article::A
+    } else if word.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        Article::An
+    } else {
+        Article::A
+    }
+}
+
+/// For multi-word nouns, like "rubber duck", only the last word is
+/// inflected.
+pub fn plural(singular: &str) -> String {
+    let (before, last) = singular.rsplit_once(' ').unwrap_or(("", singular));
+    let plural = inflect(last, IRREGULAR_PLURALS);
+    if before.is_empty() {
+        plural
+    } else {
+        format!("{before} {plural}")
+    }
+}
+
+/// For multi-word verbs, like "look at", only the first word is
+/// inflected.
+pub fn third_person_singular(verb: &str) -> String {
+    let (first, after) = verb.split_once(' ').unwrap_or((verb, ""));
+    let inflected = inflect(first, IRREGULAR_THIRD_PERSON_SINGULARS);
+    if after.is_empty() {
+        inflected
+    } else {
+        format!("{inflected} {after}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn article_goes_by_the_sound_of_the_first_word() {
+        let cases = [
+            ("hour", Article::An),
+            ("heir", Article::An),
+            ("hat", Article::A),
+            ("unicorn", Article::A),
+            ("umbrella", Article::An),
+            ("European", Article::A),
+            ("egg", Article::An),
+            ("one", Article::A),
+            ("onerous task", Article::An),
+            ("U-turn", Article::A),
+            ("old-timer", Article::An),
+        ];
+
+        for (phrase, expected) in cases {
+            assert_eq!(article(phrase), expected, "{phrase}");
+        }
+    }
+}
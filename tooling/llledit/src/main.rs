@@ -2,50 +2,540 @@ use std::{fs::File, io::{self, Read}, path::PathBuf};
 
 macro_rules! compile_time_assert {
     ($assertion: expr) => {{
-        #[allow(unknown_lints, eq_op)]
+        #[allow(unknown_lints, clippy::eq_op)]
         // Based on the const_assert macro from static_assertions;
         const _: [(); 0 - !{$assertion} as usize] = [];
     }}
 }
 
 mod printer {
-    #[derive(Default)]
+    use std::{
+        fmt,
+        io::{self, IsTerminal, Write},
+    };
+    #[cfg(test)]
+    use std::{cell::RefCell, rc::Rc};
+
+    /// The ways we can manipulate a terminal. Implementations that can't do
+    /// a given thing should approximate it as best they can.
+    pub trait Backend {
+        fn write_fmt(&self, args: fmt::Arguments<'_>);
+        fn enable_alternate_screen(&self);
+        fn disable_alternate_screen(&self);
+        fn clear(&self);
+        fn move_home(&self);
+        fn flush(&self);
+        /// Whether `clear` and `move_home` actually redraw the screen in place.
+        fn can_redraw(&self) -> bool;
+    }
+
+    pub struct Ansi;
+
+    impl Backend for Ansi {
+        fn write_fmt(&self, args: fmt::Arguments<'_>) {
+            print!("{args}");
+        }
+
+        fn enable_alternate_screen(&self) {
+            print!("\u{001b}[?1049h");
+        }
+
+        fn disable_alternate_screen(&self) {
+            print!("\u{001b}[?1049l");
+        }
+
+        fn clear(&self) {
+            print!("\u{001b}[2J");
+        }
+
+        fn move_home(&self) {
+            print!("\u{001b}[H");
+        }
+
+        fn flush(&self) {
+            let _ = io::stdout().flush();
+        }
+
+        fn can_redraw(&self) -> bool {
+            true
+        }
+    }
+
+    /// For terminals that don't understand escape codes, or output that isn't
+    /// going to a terminal at all. Since we can't clear the screen, we print a
+    /// separator between screens instead, so they can still be told apart.
+    pub struct Dumb;
+
+    impl Backend for Dumb {
+        fn write_fmt(&self, args: fmt::Arguments<'_>) {
+            print!("{args}");
+        }
+
+        fn enable_alternate_screen(&self) {}
+
+        fn disable_alternate_screen(&self) {}
+
+        fn clear(&self) {
+            println!();
+            println!("{}", "-".repeat(40));
+        }
+
+        fn move_home(&self) {}
+
+        fn flush(&self) {
+            let _ = io::stdout().flush();
+        }
+
+        fn can_redraw(&self) -> bool {
+            false
+        }
+    }
+
+    /// Records everything printed, split into screens at each `clear`, instead
+    /// of printing it. For use in tests.
+    #[cfg(test)]
+    #[derive(Clone, Default)]
+    pub struct Recording {
+        screens: Rc<RefCell<Vec<String>>>,
+    }
+
+    #[cfg(test)]
+    impl Recording {
+        pub fn screens(&self) -> Vec<String> {
+            self.screens.borrow().clone()
+        }
+
+        pub fn last_screen(&self) -> Option<String> {
+            self.screens.borrow().last().cloned()
+        }
+    }
+
+    #[cfg(test)]
+    impl Backend for Recording {
+        fn write_fmt(&self, args: fmt::Arguments<'_>) {
+            use fmt::Write;
+
+            let mut screens = self.screens.borrow_mut();
+            if screens.is_empty() {
+                screens.push(String::new());
+            }
+            let last_i = screens.len() - 1;
+            // Writing to a `String` cannot fail.
+            let _ = screens[last_i].write_fmt(args);
+        }
+
+        fn enable_alternate_screen(&self) {}
+
+        fn disable_alternate_screen(&self) {}
+
+        fn clear(&self) {
+            self.screens.borrow_mut().push(String::new());
+        }
+
+        fn move_home(&self) {}
+
+        fn flush(&self) {}
+
+        fn can_redraw(&self) -> bool {
+            true
+        }
+    }
+
     pub struct Printer {
-        enable_alt: &'static str,
-        disable_alt: &'static str,
-        clear: &'static str,
-        move_home: &'static str,
+        backend: Box<dyn Backend>,
     }
 
     impl Printer {
-        pub fn ansi() -> Self {
-            Self {
-                enable_alt: "\u{001b}[?1049h",
-                disable_alt: "\u{001b}[?1049l",
-                clear: "\u{001b}[2J",
-                move_home: "\u{001b}[H",
+        pub fn new(backend: Box<dyn Backend>) -> Self {
+            Self { backend }
+        }
+
+        /// Picks a backend based on whether stdout is a terminal, the `TERM`
+        /// environment variable, and whether escape codes can be enabled.
+        pub fn from_env() -> Self {
+            if !io::stdout().is_terminal() {
+                return Self::new(Box::new(Dumb));
+            }
+
+            if std::env::var_os("TERM").is_some_and(|term| term == "dumb") {
+                return Self::new(Box::new(Dumb));
+            }
+
+            match enable_ansi_support::enable_ansi_support() {
+                Ok(()) => Self::new(Box::new(Ansi)),
+                Err(e) => {
+                    eprintln!("{e}");
+                    Self::new(Box::new(Dumb))
+                }
             }
         }
 
+        /// Allows `write!` and `writeln!` to be used with a `Printer`.
+        pub fn write_fmt(&self, args: fmt::Arguments<'_>) {
+            self.backend.write_fmt(args);
+        }
+
         pub fn enable_alternate_screen(&self) {
-            print!("{}", self.enable_alt);
+            self.backend.enable_alternate_screen();
         }
 
         pub fn disable_alternate_screen(&self) {
-            print!("{}", self.disable_alt);
+            self.backend.disable_alternate_screen();
         }
 
         pub fn clear(&self) {
-            print!("{}", self.clear);
+            self.backend.clear();
         }
 
         pub fn move_home(&self) {
-            print!("{}", self.move_home);
+            self.backend.move_home();
+        }
+
+        pub fn flush(&self) {
+            self.backend.flush();
+        }
+
+        /// Whether we can redraw the screen in place, and read individual
+        /// keypresses, as opposed to only whole lines.
+        pub fn is_interactive(&self) -> bool {
+            self.backend.can_redraw()
+            && io::stdin().is_terminal()
+            && io::stdout().is_terminal()
         }
     }
 }
 use printer::Printer;
 
+mod flag_display {
+    use super::*;
+    use std::fmt;
+
+    pub fn adjective_order_category(flags: Flags) -> AdjectiveOrderCategory {
+        ((flags & ADJECTIVE_ORDER_MASK) >> ADJECTIVE_ORDER_SHIFT) as AdjectiveOrderCategory
+    }
+
+    /// Writes the name of the flag at `index`, including the index if the name
+    /// alone would be ambiguous.
+    fn write_flag_name(f: &mut fmt::Formatter<'_>, index: FlagIndex) -> fmt::Result {
+        match FLAG_NAMES.get(usize::from(index)) {
+            Some(&name) if name != "RESERVED" => write!(f, "{name}"),
+            _ => write!(f, "RESERVED({index})"),
+        }
+    }
+
+    fn write_category_name(
+        f: &mut fmt::Formatter<'_>,
+        category: AdjectiveOrderCategory
+    ) -> fmt::Result {
+        match ADJECTIVE_ORDER_BLOCK_NAMES.get(usize::from(category)) {
+            Some(&name) if name != "RESERVED" => write!(f, "{name}"),
+            _ => write!(f, "RESERVED({category})"),
+        }
+    }
+
+    /// Is the flag at `index` an individual flag, as opposed to part of a block?
+    fn is_individual_flag(index: FlagIndex) -> bool {
+        flag(index) & ADJECTIVE_ORDER_MASK == 0
+    }
+
+    /// Displays flags by name, with the adjective order block shown by
+    /// category name. For example:
+    /// `TRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB, adj=NONE`
+    pub struct Named(pub Flags);
+
+    impl fmt::Display for Named {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let flags = self.0;
+
+            let mut any_written = false;
+            for index in (0..FLAG_COUNT).filter(|&i| is_individual_flag(i)) {
+                if flags & flag(index) != 0 {
+                    if any_written {
+                        write!(f, " | ")?;
+                    }
+                    write_flag_name(f, index)?;
+                    any_written = true;
+                }
+            }
+
+            if any_written {
+                write!(f, ", ")?;
+            }
+            write!(f, "adj=")?;
+            write_category_name(f, adjective_order_category(flags))
+        }
+    }
+
+    /// Displays what changed between two sets of flags. For example:
+    /// `+PLURAL_NOUN -SINGULAR_NOUN, adj=NONE -> SIZE`
+    pub struct Diff {
+        pub old: Flags,
+        pub new: Flags,
+    }
+
+    impl fmt::Display for Diff {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let Self { old, new } = *self;
+
+            let mut any_written = false;
+            for (sign, added) in [('+', true), ('-', false)] {
+                for index in (0..FLAG_COUNT).filter(|&i| is_individual_flag(i)) {
+                    let was_set = old & flag(index) != 0;
+                    let is_set = new & flag(index) != 0;
+                    if was_set != is_set && is_set == added {
+                        if any_written {
+                            write!(f, " ")?;
+                        }
+                        write!(f, "{sign}")?;
+                        write_flag_name(f, index)?;
+                        any_written = true;
+                    }
+                }
+            }
+
+            let old_category = adjective_order_category(old);
+            let new_category = adjective_order_category(new);
+            if old_category != new_category {
+                if any_written {
+                    write!(f, ", ")?;
+                }
+                write!(f, "adj=")?;
+                write_category_name(f, old_category)?;
+                write!(f, " -> ")?;
+                write_category_name(f, new_category)?;
+                any_written = true;
+            }
+
+            if !any_written {
+                write!(f, "(no changes)")?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const SIZE: Flags = 6 << ADJECTIVE_ORDER_SHIFT;
+
+        #[test]
+        fn named_shows_flags_and_the_adjective_order_category_by_name() {
+            assert_eq!(
+                Named(TRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB).to_string(),
+                "TRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB, adj=NONE",
+            );
+            assert_eq!(Named(SINGULAR_NOUN | SIZE).to_string(), "SINGULAR_NOUN, adj=SIZE");
+            assert_eq!(Named(0).to_string(), "adj=NONE");
+            assert_eq!(
+                Named(1 << 3 | 15 << ADJECTIVE_ORDER_SHIFT).to_string(),
+                "RESERVED(3), adj=RESERVED(15)",
+            );
+        }
+
+        #[test]
+        fn diff_shows_what_changed() {
+            assert_eq!(
+                Diff{ old: SINGULAR_NOUN, new: PLURAL_NOUN | SIZE }.to_string(),
+                "+PLURAL_NOUN -SINGULAR_NOUN, adj=NONE -> SIZE",
+            );
+            assert_eq!(Diff{ old: SIZE, new: 0 }.to_string(), "adj=SIZE -> NONE");
+            assert_eq!(Diff{ old: MASS_NOUN, new: MASS_NOUN }.to_string(), "(no changes)");
+        }
+    }
+}
+
+mod flag_editor {
+    use super::*;
+    use crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        terminal,
+    };
+
+    #[derive(Clone, Copy)]
+    enum Row {
+        Flag(FlagIndex),
+        AdjectiveOrder(AdjectiveOrderCategory),
+    }
+
+    fn rows() -> Vec<Row> {
+        let mut rows = Vec::with_capacity(
+            FLAG_NAMES.len() + ADJECTIVE_ORDER_BLOCK_NAMES.len()
+        );
+
+        for (i, name) in FLAG_NAMES.iter().enumerate() {
+            if *name != "RESERVED" {
+                rows.push(Row::Flag(i as FlagIndex));
+            }
+        }
+
+        for (i, name) in ADJECTIVE_ORDER_BLOCK_NAMES.iter().enumerate() {
+            if *name != "RESERVED" {
+                rows.push(Row::AdjectiveOrder(i as AdjectiveOrderCategory));
+            }
+        }
+
+        rows
+    }
+
+    /// Puts the terminal into raw mode for as long as this is alive, so we
+    /// get keypresses as they happen.
+    struct RawMode;
+
+    impl RawMode {
+        fn enable() -> io::Result<Self> {
+            terminal::enable_raw_mode()?;
+            Ok(Self)
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // If this fails there isn't much we can do about it, and we'd
+            // rather not panic in a drop.
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+
+    /// Lets the user edit a copy of `ll.flags` with the arrow keys and space
+    /// bar, until they either finish, or ask to edit the lexeme instead.
+    /// Returns the commands that will make the same changes to `ll`.
+    /// `prev_flags` are shown for reference, if present.
+    pub fn run(
+        p: &Printer,
+        title: &str,
+        ll: &LL,
+        prev_flags: Option<Flags>,
+    ) -> io::Result<Box<[FlagsCommand]>> {
+        let rows = rows();
+        let mut cursor = 0;
+
+        // We edit a copy so the changes can be shown as they happen, while
+        // still reporting them as commands, so the caller can apply them.
+        let mut ll = ll.clone();
+        let mut commands = Vec::new();
+
+        let _raw_mode = RawMode::enable()?;
+
+        loop {
+            render(p, title, &ll, prev_flags, &rows, cursor);
+
+            let key = match event::read()? {
+                // Windows reports releases as well as presses.
+                Event::Key(KeyEvent { kind: KeyEventKind::Release, .. }) => continue,
+                Event::Key(key) => key,
+                _ => continue,
+            };
+
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "Interrupted"));
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    cursor = cursor.checked_sub(1).unwrap_or(rows.len() - 1);
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    cursor = (cursor + 1) % rows.len();
+                }
+                KeyCode::Home => {
+                    cursor = 0;
+                }
+                KeyCode::End => {
+                    cursor = rows.len() - 1;
+                }
+                KeyCode::Char(' ') => {
+                    let command = match rows[cursor] {
+                        Row::Flag(index) => FlagsCommand::Toggle(index),
+                        Row::AdjectiveOrder(category) => FlagsCommand::SetAdjectiveOrder(category),
+                    };
+                    handle_commands(&mut ll, &[command]);
+                    commands.push(command);
+                }
+                KeyCode::Enter | KeyCode::Char('f') => {
+                    commands.push(FlagsCommand::FinishedFlags);
+                    return Ok(commands.into());
+                }
+                KeyCode::Char('e') => {
+                    commands.push(FlagsCommand::EditLexeme);
+                    return Ok(commands.into());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(
+        p: &Printer,
+        title: &str,
+        ll: &LL,
+        prev_flags: Option<Flags>,
+        rows: &[Row],
+        cursor: usize,
+    ) {
+        // Raw mode means "\n" no longer implies a carriage return.
+        const NL: &str = "\r\n";
+
+        p.clear();
+        p.move_home();
+
+        write!(p, "{title}{NL}");
+        write!(p, "{:#}{NL}", ll.lexeme);
+        if let Some(prev_flags) = prev_flags {
+            write!(p, "{}{NL}", flag_display::Named(prev_flags));
+            write!(p, "{}{NL}{NL}", flag_display::Diff{ old: prev_flags, new: ll.flags });
+        }
+        write!(p, ">{:#b}{NL}", ll.flags);
+        write!(p, ">{}{NL}", flag_display::Named(ll.flags));
+        write!(p, "{NL}");
+
+        let category = (ll.flags & ADJECTIVE_ORDER_MASK) >> ADJECTIVE_ORDER_SHIFT;
+
+        let mut previous_row = None;
+        for (i, row) in rows.iter().enumerate() {
+            match (previous_row, row) {
+                (None, Row::Flag(_)) => {
+                    write!(p, "Flags:{NL}");
+                }
+                (None | Some(Row::Flag(_)), Row::AdjectiveOrder(_)) => {
+                    write!(p, "Adjective Order Block:{NL}");
+                }
+                _ => {}
+            }
+            previous_row = Some(*row);
+
+            let pointer = if i == cursor { '>' } else { ' ' };
+
+            match *row {
+                Row::Flag(index) => {
+                    let mark = if ll.flags & flag(index) != 0 { 'x' } else { ' ' };
+                    write!(
+                        p,
+                        "{pointer} [{mark}] {:2} {}{NL}",
+                        index,
+                        FLAG_NAMES[usize::from(index)]
+                    );
+                }
+                Row::AdjectiveOrder(c) => {
+                    let mark = if category == c as Flags { '*' } else { ' ' };
+                    write!(
+                        p,
+                        "{pointer} ({mark}) {:2} {}{NL}",
+                        c,
+                        ADJECTIVE_ORDER_BLOCK_NAMES[usize::from(c)]
+                    );
+                }
+            }
+        }
+
+        write!(p, "{NL}");
+        write!(p, "Up/Down) Move. Space) Toggle/Select.{NL}");
+        write!(p, "e) Edit lexeme. f/Enter) Finished editing flags.{NL}");
+
+        p.flush();
+    }
+}
+
 type ErrMsg = &'static str;
 
 type Flags = u32;
@@ -53,7 +543,102 @@ type Flags = u32;
 type FlagIndex = u8;
 type AdjectiveOrderCategory = u8;
 
-#[derive(Clone, Copy, Debug)]
+/// The number of bits in the 3 flags bytes of a block.
+const FLAG_COUNT: FlagIndex = 24;
+const FLAG_INDEX_RANGE_ERROR: ErrMsg = "Flag indexes must be from 0 to 23 inclusive";
+
+const ADJECTIVE_ORDER_SHIFT: Flags = 16;
+const ADJECTIVE_ORDER_MASK: Flags = 0b1111 << ADJECTIVE_ORDER_SHIFT;
+/// The number of values that fit in the 4 adjective order block bits.
+const ADJECTIVE_ORDER_CATEGORY_COUNT: AdjectiveOrderCategory = 16;
+const ADJECTIVE_ORDER_CATEGORY_RANGE_ERROR: ErrMsg =
+    "Adjective order categories must be from 0 to 15 inclusive";
+
+const _: () = assert!(FLAG_COUNT as u32 <= Flags::BITS);
+const _: () = assert!(ADJECTIVE_ORDER_SHIFT + 4 <= FLAG_COUNT as Flags);
+
+// The flags that have meanings we act on. See `FLAG_NAMES` for the rest.
+const SINGULAR_NOUN: Flags = 1 << 0;
+const PLURAL_NOUN: Flags = 1 << 1;
+const MASS_NOUN: Flags = 1 << 2;
+const INTRANSITIVE_VERB: Flags = 1 << 4;
+const TRANSITIVE_VERB: Flags = 1 << 5;
+const THIRD_PERSON_SINGULAR_VERB: Flags = 1 << 8;
+const FIRST_PERSON_SINGULAR_VERB: Flags = 1 << 10;
+
+const FLAG_NAMES: [&str; 16] = [
+    "SINGULAR_NOUN",
+    "PLURAL_NOUN",
+    "MASS_NOUN",
+    "RESERVED",
+    "INTRANSITIVE_VERB",
+    "TRANSITIVE_VERB",
+    "RESERVED",
+    "RESERVED",
+    "THIRD_PERSON_SINGULAR_VERB",
+    "RESERVED",
+    "FIRST_PERSON_SINGULAR_VERB",
+    "RESERVED",
+    "RESERVED",
+    "RESERVED",
+    "RESERVED",
+    "RESERVED",
+];
+
+// Used in compile-time asserts.
+#[allow(dead_code)]
+const MAX_FLAG_NAME_LEN: usize = {
+    let mut max_len = 0;
+    let mut i = 0;
+    while i < FLAG_NAMES.len() {
+        let len = FLAG_NAMES[i].len();
+        if len > max_len {
+            max_len = len;
+        }
+        i += 1;
+    }
+    max_len
+};
+
+// The first and last adjective order categories that are not reserved.
+const ADJECTIVE_ORDER_QUANTITY: AdjectiveOrderCategory = 4;
+const ADJECTIVE_ORDER_MATERIAL: AdjectiveOrderCategory = 12;
+
+const ADJECTIVE_ORDER_BLOCK_NAMES: [&str; 16] = [
+    "NONE",
+    "RESERVED",
+    "RESERVED",
+    "RESERVED",
+    "QUANTITY",
+    "OBSERVATION",
+    "SIZE",
+    "PHYSICAL",
+    "SHAPE",
+    "AGE",
+    "COLOUR",
+    "ORIGIN",
+    "MATERIAL",
+    "RESERVED",
+    "RESERVED",
+    "RESERVED",
+];
+
+// Used in compile-time asserts.
+#[allow(dead_code)]
+const MAX_ADJECTIVE_ORDER_BLOCK_NAME_LEN: usize = {
+    let mut max_len = 0;
+    let mut i = 0;
+    while i < ADJECTIVE_ORDER_BLOCK_NAMES.len() {
+        let len = ADJECTIVE_ORDER_BLOCK_NAMES[i].len();
+        if len > max_len {
+            max_len = len;
+        }
+        i += 1;
+    }
+    max_len
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum FlagsCommand {
     Set(FlagIndex),
     Toggle(FlagIndex),
@@ -63,6 +648,35 @@ enum FlagsCommand {
     FinishedFlags,
 }
 
+/// Formats the command in the syntax `parse_flags_commands` accepts.
+impl std::fmt::Display for FlagsCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use FlagsCommand::*;
+        match *self {
+            Set(index) => write!(f, "s{index}"),
+            Toggle(index) => write!(f, "t{index}"),
+            Unset(index) => write!(f, "u{index}"),
+            SetAdjectiveOrder(category) => write!(f, "a{category}"),
+            EditLexeme => write!(f, "e"),
+            FinishedFlags => write!(f, "f"),
+        }
+    }
+}
+
+/// The inverse of `parse_flags_commands`, except that the output is not
+/// necessarily identical to whatever the commands were originally parsed from.
+fn flags_commands_to_line(commands: &[FlagsCommand]) -> String {
+    let mut output = String::with_capacity(commands.len() * 4);
+    for (i, command) in commands.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        output.push_str(&command.to_string());
+    }
+    output.push('\n');
+    output
+}
+
 fn parse_flags_commands(input: &str) -> Result<Box<[FlagsCommand]>, ErrMsg> {
     // We'd rather set digit_buffer_i to 0 (inside push_buffered) when it isn't
     // needed than miss setting it to 0 when we should.
@@ -87,51 +701,67 @@ fn parse_flags_commands(input: &str) -> Result<Box<[FlagsCommand]>, ErrMsg> {
     // So half the input length is a (generous) upper bound.
     let mut output = Vec::with_capacity(input.len() / 2);
 
-    const MAX_INDEX_DIGITS: u32 = FlagIndex::MAX.ilog10() + 1;
+    // Anything with more digits than the largest valid value is out of range,
+    // whether it is a flag index or an adjective order category.
+    const MAX_INDEX_DIGITS: u32 = {
+        let max = if FLAG_COUNT > ADJECTIVE_ORDER_CATEGORY_COUNT {
+            FLAG_COUNT
+        } else {
+            ADJECTIVE_ORDER_CATEGORY_COUNT
+        };
+        (max - 1).ilog10() + 1
+    };
     let mut digit_buffer = [0u8; MAX_INDEX_DIGITS as _];
     let mut digit_buffer_i = 0;
 
-    for c in input.chars() {
-        macro_rules! push_buffered {
-            ($command_fn: path) => ({
-                if digit_buffer_i == 0 {
-                    // Nothing to push yet.
-                } else {
-                    let s = std::str::from_utf8(&digit_buffer[..digit_buffer_i])
-                        .map_err(|_| "non-UTF8 digit_buffer")?;
-
-                    let flag_index =
-                        s
-                        .parse()
-                        .map_err(|_| "non-decimal-digit numeral byte in digit_buffer")?;
+    macro_rules! push_buffered {
+        ($command_fn: path, $count: expr, $range_error: expr) => ({
+            if digit_buffer_i == 0 {
+                // Nothing to push yet.
+            } else {
+                let s = std::str::from_utf8(&digit_buffer[..digit_buffer_i])
+                    .map_err(|_| "non-UTF8 digit_buffer")?;
 
-                    output.push($command_fn(flag_index));
+                let value: u8 =
+                    s
+                    .parse()
+                    .map_err(|_| $range_error)?;
 
-                    digit_buffer_i = 0;
+                if value >= $count {
+                    return Err($range_error);
                 }
-            })
-        }
 
-        macro_rules! push_buffered_if_needed {
-            () => ({
-                match state {
-                    SetIndex => {
-                        push_buffered!(Set);
-                    },
-                    ToggleIndex => {
-                        push_buffered!(Toggle);
-                    },
-                    UnsetIndex => {
-                        push_buffered!(Unset);
-                    },
-                    SetAdjectiveOrderCategory => {
-                        push_buffered!(SetAdjectiveOrder);
-                    }
-                }
-            })
-        }
+                output.push($command_fn(value));
 
+                digit_buffer_i = 0;
+            }
+        })
+    }
+
+    macro_rules! push_buffered_if_needed {
+        () => ({
+            match state {
+                SetIndex => {
+                    push_buffered!(Set, FLAG_COUNT, FLAG_INDEX_RANGE_ERROR);
+                },
+                ToggleIndex => {
+                    push_buffered!(Toggle, FLAG_COUNT, FLAG_INDEX_RANGE_ERROR);
+                },
+                UnsetIndex => {
+                    push_buffered!(Unset, FLAG_COUNT, FLAG_INDEX_RANGE_ERROR);
+                },
+                SetAdjectiveOrderCategory => {
+                    push_buffered!(
+                        SetAdjectiveOrder,
+                        ADJECTIVE_ORDER_CATEGORY_COUNT,
+                        ADJECTIVE_ORDER_CATEGORY_RANGE_ERROR
+                    );
+                }
+            }
+        })
+    }
 
+    for c in input.chars() {
         match c {
             ','|' '|'\n' => push_buffered_if_needed!(),
             's' => {
@@ -147,6 +777,12 @@ fn parse_flags_commands(input: &str) -> Result<Box<[FlagsCommand]>, ErrMsg> {
                 state = UnsetIndex;
             },
             '0'..='9' => {
+                if digit_buffer_i >= digit_buffer.len() {
+                    return Err(match state {
+                        SetAdjectiveOrderCategory => ADJECTIVE_ORDER_CATEGORY_RANGE_ERROR,
+                        SetIndex | ToggleIndex | UnsetIndex => FLAG_INDEX_RANGE_ERROR,
+                    });
+                }
                 digit_buffer[digit_buffer_i] = c.try_into()
                     .expect("should be in 0-9");
                 digit_buffer_i += 1;
@@ -169,627 +805,2249 @@ fn parse_flags_commands(input: &str) -> Result<Box<[FlagsCommand]>, ErrMsg> {
                 if c < ' ' {
                     return Err("got unexpected char less than ' '");
                 } else if c > '\u{7f}' {
-                    return Err("got unexpected char more than \\u{{7f}}");
+                    return Err("got unexpected char more than \\u{7f}");
                 } else {
-                    return Err("got unexpected char between ' ' and \\u{{7f}} inclusive");
+                    return Err("got unexpected char between ' ' and \\u{7f} inclusive");
                 }
             }
         }
     }
+    // The input may not end with a newline, so there may be one last index
+    // waiting.
+    push_buffered_if_needed!();
 
     Ok(output.into())
 }
 
+fn print_flags_commands_help(p: &Printer) {
+    writeln!(p, "To change the flags pick a operation prefix:");
+    writeln!(p, "s) Set bits. t) Toggle bits. u) Un-set bits.");
+    writeln!(p, "... then enter it followed by a comma-separated");
+    writeln!(p, "list of bit indexes.");
+    writeln!(p, "To change a block value enter a block prefix:");
+    writeln!(p, "a) adjective order.");
+    writeln!(p, "... then enter it followed by the desired value.");
+    writeln!(p);
+    writeln!(p, "Flags:");
+    {
+        let half_len = FLAG_NAMES.len().div_ceil(2);
+
+        for (i, first_name) in FLAG_NAMES.iter().enumerate().take(half_len) {
+            let i2 = half_len + i;
+            if let Some(second_name) = FLAG_NAMES.get(i2) {
+                // assert format width is large enough
+                compile_time_assert!(
+                    30 >= MAX_FLAG_NAME_LEN
+                );
+                writeln!(p, "{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
+            } else {
+                writeln!(p, "{first_name:>30}:{i:2}");
+            }
+        }
+    }
+    writeln!(p, "Adjective Order Block:");
+    {
+        let half_len = ADJECTIVE_ORDER_BLOCK_NAMES.len().div_ceil(2);
+
+        for (i, first_name) in ADJECTIVE_ORDER_BLOCK_NAMES.iter().enumerate().take(half_len) {
+            let i2 = half_len + i;
+            if let Some(second_name) = ADJECTIVE_ORDER_BLOCK_NAMES.get(i2) {
+                // assert format width is large enough
+                compile_time_assert!(
+                    30 >= MAX_ADJECTIVE_ORDER_BLOCK_NAME_LEN
+                );
+                writeln!(p, "{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
+            } else {
+                writeln!(p, "{first_name:>30}:{i:2}");
+            }
+        }
+    }
+    writeln!(p, "e) Edit lexeme. f) Finished editing flags.");
+}
+
 const V0_HEADER: [u8; 4] = [b'l', b'l', b'l', 0];
 const V0_MIN_LENGTH: u8 = 4;
+const V0_MAX_LENGTH: u8 = 0x7F;
+const V1_HEADER: [u8; 4] = [b'l', b'l', b'l', 1];
+const V1_MIN_LENGTH: u16 = 5;
+const V1_MAX_LENGTH: u16 = 0x7FFF;
+
+/// The versions of the .lll format that we know how to read and write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Version {
+    V0,
+    V1,
+}
 
-mod lexeme {
-    use super::*;
-
-    pub const MAX_LENGTH: u8 = 127 - V0_MIN_LENGTH;
-    pub const MAX_LENGTH_ERROR: ErrMsg = "Lexemes canot be more than 123 bytes long!";
-
-    #[derive(Clone)]
-    pub struct Lexeme([u8; MAX_LENGTH as _]);
+impl Version {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::V0),
+            1 => Some(Self::V1),
+            _ => None,
+        }
+    }
 
-    impl Default for Lexeme {
-        fn default() -> Self {
-            Self([0; MAX_LENGTH as _])
+    fn header(self) -> [u8; 4] {
+        match self {
+            Self::V0 => V0_HEADER,
+            Self::V1 => V1_HEADER,
         }
     }
 
-    impl std::fmt::Display for Lexeme {
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            match std::str::from_utf8(&self.0) {
-                Ok(s) => if f.alternate() {
-                    write!(f, "\"{s}\"")
-                } else {
-                    write!(f, "{s}")
-                },
-                Err(e) => write!(f, "{e}"),
-            }
+    /// The length of the part of each block before the lexeme.
+    fn block_header_length(self) -> usize {
+        match self {
+            Self::V0 => usize::from(V0_MIN_LENGTH),
+            Self::V1 => usize::from(V1_MIN_LENGTH),
+        }
+    }
 
+    fn max_lexeme_length(self) -> usize {
+        match self {
+            Self::V0 => usize::from(V0_MAX_LENGTH - V0_MIN_LENGTH),
+            Self::V1 => usize::from(V1_MAX_LENGTH - V1_MIN_LENGTH),
         }
     }
 
-    impl TryFrom<&[u8]> for Lexeme {
-        type Error = ErrMsg;
+    /// The lowest version that can hold a lexeme, or extension value,
+    /// `body_len` bytes long.
+    fn lowest_for(body_len: usize) -> Self {
+        if body_len <= Self::V0.max_lexeme_length() {
+            Self::V0
+        } else {
+            Self::V1
+        }
+    }
 
-        fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-            // Pass through str so we are sure that all `Lexeme`s are valid UTF-8
-            match std::str::from_utf8(value) {
-                Err(_) => Err("Potential lexeme was not valid UTF-8"),
-                Ok(s) => Self::try_from(s)
+    /// Reads the FEF and the length of the block starting at the start of
+    /// `bytes`. Returns `None` if `bytes` is too short to hold the length.
+    fn read_length(self, bytes: &[u8]) -> Option<(bool, usize)> {
+        match self {
+            Self::V0 => {
+                let byte = *bytes.first()?;
+                Some((byte & 0x80 == 0x80, usize::from(byte & 0x7F)))
+            }
+            Self::V1 => {
+                let raw = u16::from_le_bytes([*bytes.first()?, *bytes.get(1)?]);
+                Some((raw & 0x8000 == 0x8000, usize::from(raw & 0x7FFF)))
             }
         }
     }
 
-    impl TryFrom<&str> for Lexeme {
-        type Error = ErrMsg;
-
-        fn try_from(value: &str) -> Result<Self, Self::Error> {
-            let value = value.trim();
+    /// The offset of the flags within a block.
+    fn flags_offset(self) -> usize {
+        self.block_header_length() - 3
+    }
+}
 
-            if value.is_empty() {
-                Err("")
-            } else if value.len() > usize::from(MAX_LENGTH) {
-                Err(MAX_LENGTH_ERROR)
-            } else {
-                let mut lexeme = [0; MAX_LENGTH as _];
+mod lexeme {
+    use super::*;
 
-                for (i, b) in value.as_bytes().iter().enumerate() {
-                    lexeme[i] = *b;
-                }
+    pub const MAX_LENGTH: u16 = V1_MAX_LENGTH - V1_MIN_LENGTH;
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Error {
+        Empty,
+        TooLong{
+            /// The length in bytes of the would-be lexeme.
+            len: usize,
+            /// The length in characters of the would-be lexeme.
+            char_count: usize,
+            /// The index of the first character that does not entirely fit
+            /// within `MAX_LENGTH` bytes.
+            crossed_at_char: usize,
+        },
+        /// `valid_up_to` is the byte offset of the first invalid byte.
+        InvalidUtf8{ valid_up_to: usize },
+    }
 
-                Ok(Lexeme(lexeme))
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match *self {
+                Error::Empty => write!(f, "Lexemes cannot be empty!"),
+                Error::TooLong{ len, char_count, crossed_at_char } => write!(
+                    f,
+                    "Lexemes cannot be more than {MAX_LENGTH} bytes long! \
+                    This one is {len} bytes ({char_count} characters) long, \
+                    so the limit is crossed at character {}.",
+                    // Counting from 1 reads better to people.
+                    crossed_at_char + 1,
+                ),
+                Error::InvalidUtf8{ valid_up_to } => write!(
+                    f,
+                    "Lexemes must be valid UTF-8, but byte {valid_up_to} of this one was not."
+                ),
             }
         }
     }
 
-    impl Lexeme {
-        pub fn len(&self) -> u8 {
-            self.as_str().len() as u8
+    impl std::error::Error for Error {}
+
+    /// Returns the longest prefix of `s` that is at most `MAX_LENGTH` bytes
+    /// long, without splitting any characters.
+    pub fn truncate(s: &str) -> &str {
+        let mut end = usize::from(MAX_LENGTH);
+        if end >= s.len() {
+            return s
         }
 
-        pub fn bytes(&self) -> &[u8] {
-            self.as_str().as_bytes()
+        while !s.is_char_boundary(end) {
+            end -= 1;
         }
 
-        fn as_str(&self) -> &str {
-            // This module only exposes ways to create `Lexeme`s that ensure this
-            // cannot fail.
-            std::str::from_utf8(&self.0)
-                .expect("all lexemes should be valid UTF-8")
-                .trim_end_matches('\0')
+        &s[..end]
+    }
+
+    /// Checks that `s` could be a `Lexeme` exactly as it is, without making
+    /// one.
+    pub fn check(s: &str) -> Result<(), Error> {
+        if s.is_empty() {
+            Err(Error::Empty)
+        } else if s.len() > usize::from(MAX_LENGTH) {
+            Err(Error::TooLong{
+                len: s.len(),
+                char_count: s.chars().count(),
+                crossed_at_char: truncate(s).chars().count(),
+            })
+        } else {
+            Ok(())
         }
     }
-}
-use lexeme::Lexeme;
 
-// Labelled Lexeme
-#[derive(Clone, Default)]
-struct LL {
+    /// A non-empty UTF-8 string, at most `MAX_LENGTH` bytes long. Only the
+    /// actual bytes of the lexeme are stored.
+    #[derive(Clone)]
+    pub struct Lexeme(Box<str>);
+
+    impl std::fmt::Display for Lexeme {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let s = self.as_str();
+            if f.alternate() {
+                write!(f, "\"{s}\"")
+            } else {
+                write!(f, "{s}")
+            }
+        }
+    }
+
+    impl Lexeme {
+        /// For text typed in by a user, so surrounding whitespace, including
+        /// the newline from pressing enter, is removed.
+        pub fn from_input(input: &str) -> Result<Self, Error> {
+            Self::from_str_exact(input.trim())
+        }
+
+        /// For bytes read from a file, which are kept exactly as they are.
+        pub fn from_disk(bytes: &[u8]) -> Result<Self, Error> {
+            // Pass through str so we are sure that all `Lexeme`s are valid UTF-8
+            let s = std::str::from_utf8(bytes)
+                .map_err(|e| Error::InvalidUtf8{ valid_up_to: e.valid_up_to() })?;
+
+            Self::from_str_exact(s)
+        }
+
+        fn from_str_exact(s: &str) -> Result<Self, Error> {
+            check(s)?;
+            Ok(Lexeme(s.into()))
+        }
+
+        pub fn len(&self) -> u16 {
+            // This module only exposes ways to create `Lexeme`s that ensure
+            // this fits.
+            self.0.len() as u16
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            self.0.as_bytes()
+        }
+
+        pub fn as_str(&self) -> &str {
+            &self.0
+        }
+    }
+}
+use lexeme::Lexeme;
+
+/// Extra information about a lexeme, stored in extension blocks, that is,
+/// blocks with the FEF set, directly after the lexeme's block. See the
+/// "Extension Blocks" section of LLL.md.
+mod extension {
+    use super::*;
+    use std::borrow::Cow;
+
+    pub const METADATA_KIND: u8 = 1;
+    pub const RELATION_KIND: u8 = 2;
+
+    pub const FREQUENCY_KEY: u8 = 1;
+    pub const NOTE_KEY: u8 = 2;
+    pub const SOURCE_KEY: u8 = 3;
+    pub const TAG_KEY: u8 = 4;
+    pub const ARTICLE_KEY: u8 = 5;
+    pub const CASE_KEY: u8 = 6;
+
+    /// The only value for `CASE_KEY` defined so far.
+    const KEEP_CASE: &str = "keep";
+
+    pub const PLURAL_KEY: u8 = 1;
+    pub const THIRD_PERSON_SINGULAR_KEY: u8 = 2;
+
+    /// Ways a lexeme can be a form of the same word as another lexeme. Each
+    /// relation is stored on the base form, that is, the singular noun or
+    /// the first person verb, and points at the other form.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Relation {
+        Plural,
+        ThirdPersonSingular,
+    }
+
+    impl Relation {
+        pub const ALL: [Self; 2] = [Self::Plural, Self::ThirdPersonSingular];
+
+        fn from_key(key: u8) -> Option<Self> {
+            match key {
+                PLURAL_KEY => Some(Self::Plural),
+                THIRD_PERSON_SINGULAR_KEY => Some(Self::ThirdPersonSingular),
+                _ => None,
+            }
+        }
+
+        fn key(self) -> u8 {
+            match self {
+                Self::Plural => PLURAL_KEY,
+                Self::ThirdPersonSingular => THIRD_PERSON_SINGULAR_KEY,
+            }
+        }
+
+        /// What the lexeme pointed at is, relative to the base form.
+        pub fn name(self) -> &'static str {
+            match self {
+                Self::Plural => "plural",
+                Self::ThirdPersonSingular => "third person",
+            }
+        }
+
+        /// What the base form is, relative to the lexeme pointed at.
+        pub fn inverse_name(self) -> &'static str {
+            match self {
+                Self::Plural => "singular",
+                Self::ThirdPersonSingular => "first person",
+            }
+        }
+    }
+
+    /// Returns the index of the lexeme that is the `relation` of the one at
+    /// `index`. For example, the plural of a singular noun.
+    pub fn related(lll: &[LL], index: usize, relation: Relation) -> Option<usize> {
+        lll.get(index)?
+            .extensions
+            .iter()
+            .rev()
+            .find_map(|extension| match *extension {
+                Extension::Related{ relation: r, index } if r == relation => Some(index),
+                _ => None,
+            })
+    }
+
+    /// Returns the index of the lexeme that has the one at `index` as its
+    /// `relation`. For example, the singular of a plural noun.
+    pub fn related_from(lll: &[LL], index: usize, relation: Relation) -> Option<usize> {
+        (0..lll.len()).find(|&i| related(lll, i, relation) == Some(index))
+    }
+
+    /// Extension values have the same room as lexemes do.
+    pub const MAX_VALUE_LENGTH: u16 = lexeme::MAX_LENGTH;
+
+    /// An extension block borrowed from the bytes of a .lll file.
+    #[derive(Clone, Copy)]
+    pub struct ExtensionRef<'bytes> {
+        pub kind: u8,
+        pub key: u8,
+        pub value: &'bytes [u8],
+    }
+
+    /// An iterator over the extension blocks following a lexeme's block.
+    #[derive(Clone, Copy)]
+    pub struct Extensions<'bytes> {
+        /// Zero or more complete extension blocks.
+        bytes: &'bytes [u8],
+        version: Version,
+    }
+
+    impl<'bytes> Extensions<'bytes> {
+        /// `bytes` must be made up of complete extension blocks, as checked
+        /// by `Blocks`.
+        pub fn new(bytes: &'bytes [u8], version: Version) -> Self {
+            Self { bytes, version }
+        }
+    }
+
+    impl<'bytes> Iterator for Extensions<'bytes> {
+        type Item = ExtensionRef<'bytes>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let (_, len) = self.version.read_length(self.bytes)?;
+            if len < self.version.block_header_length() || len > self.bytes.len() {
+                self.bytes = &[];
+                return None
+            }
+
+            let (block, rest) = self.bytes.split_at(len);
+            self.bytes = rest;
+
+            let offset = self.version.flags_offset();
+            Some(ExtensionRef {
+                kind: block[offset],
+                key: block[offset + 1],
+                value: &block[self.version.block_header_length()..],
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Extension {
+        /// How common the lexeme is, relative to other lexemes.
+        Frequency(u32),
+        /// Free text for people editing the file.
+        Note(Box<str>),
+        /// Where the lexeme came from.
+        Source(Box<str>),
+        /// A label for grouping lexemes. A lexeme can have any number of tags.
+        Tag(Box<str>),
+        /// The indefinite article to use before the lexeme, for when it
+        /// cannot be told from the spelling.
+        Article(inflection::Article),
+        /// The lexeme should be written exactly as it is, even at the start
+        /// of a sentence. For proper nouns like "iPhone".
+        KeepCase,
+        /// The lexeme at `index` is the `relation` of this one.
+        Related{ relation: Relation, index: usize },
+        /// An extension we do not understand, kept so that it can be written
+        /// back out unchanged.
+        Unknown{ kind: u8, key: u8, value: Box<[u8]> },
+    }
+
+    impl From<ExtensionRef<'_>> for Extension {
+        fn from(ExtensionRef{ kind, key, value }: ExtensionRef<'_>) -> Self {
+            let text = || std::str::from_utf8(value).ok().map(Box::from);
+
+            let known = match (kind, key) {
+                (METADATA_KIND, FREQUENCY_KEY) => value.try_into()
+                    .ok()
+                    .map(|bytes| Self::Frequency(u32::from_le_bytes(bytes))),
+                (METADATA_KIND, NOTE_KEY) => text().map(Self::Note),
+                (METADATA_KIND, SOURCE_KEY) => text().map(Self::Source),
+                (METADATA_KIND, TAG_KEY) => text().map(Self::Tag),
+                (METADATA_KIND, ARTICLE_KEY) => std::str::from_utf8(value)
+                    .ok()
+                    .and_then(inflection::Article::from_str)
+                    .map(Self::Article),
+                (METADATA_KIND, CASE_KEY) if value == KEEP_CASE.as_bytes() => Some(Self::KeepCase),
+                (RELATION_KIND, key) => Relation::from_key(key)
+                    .zip(value.try_into().ok())
+                    .map(|(relation, bytes)| Self::Related{
+                        relation,
+                        index: u32::from_le_bytes(bytes) as usize,
+                    }),
+                _ => None,
+            };
+
+            known.unwrap_or_else(|| Self::Unknown{ kind, key, value: value.into() })
+        }
+    }
+
+    impl std::fmt::Display for Extension {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Frequency(frequency) => write!(f, "frequency: {frequency}"),
+                Self::Note(note) => write!(f, "note: {note}"),
+                Self::Source(source) => write!(f, "source: {source}"),
+                Self::Tag(tag) => write!(f, "tag: {tag}"),
+                Self::Article(article) => write!(f, "article: {}", article.as_str()),
+                Self::KeepCase => write!(f, "case: {KEEP_CASE}"),
+                Self::Related{ relation, index } => write!(f, "{}: @{index}", relation.name()),
+                Self::Unknown{ kind, key, value } => write!(
+                    f,
+                    "unknown (kind {kind}, key {key}): {} bytes",
+                    value.len()
+                ),
+            }
+        }
+    }
+
+    impl Extension {
+        /// Parses a line like "note: some text", or "plural: boots", as typed
+        /// by a user. `lookup` finds the index of a lexeme, given either the
+        /// lexeme or its index, as typed.
+        pub fn from_input(
+            input: &str,
+            lookup: impl Fn(&str) -> Option<usize>,
+        ) -> Result<Self, ErrMsg> {
+            let (name, value) = input.split_once(':')
+                .ok_or("Metadata should look like \"name: value\"")?;
+
+            let value = value.trim();
+            if value.is_empty() {
+                return Err("Metadata values cannot be empty")
+            }
+            if value.len() > usize::from(MAX_VALUE_LENGTH) {
+                return Err("Metadata values cannot be longer than lexemes")
+            }
+
+            match name.trim() {
+                "frequency" => value.parse()
+                    .map(Self::Frequency)
+                    .map_err(|_| "Frequencies must be whole numbers from 0 to 4294967295"),
+                "note" => Ok(Self::Note(value.into())),
+                "source" => Ok(Self::Source(value.into())),
+                "tag" => Ok(Self::Tag(value.into())),
+                "article" => inflection::Article::from_str(value)
+                    .map(Self::Article)
+                    .ok_or("Articles must be either a or an"),
+                "case" if value == KEEP_CASE => Ok(Self::KeepCase),
+                "case" => Err("The only case is keep"),
+                name => match Relation::ALL.into_iter().find(|r| r.name() == name) {
+                    Some(relation) => lookup(value)
+                        // Indexes are stored in 4 bytes.
+                        .filter(|&index| u32::try_from(index).is_ok())
+                        .map(|index| Self::Related{ relation, index })
+                        .ok_or("No lexeme matches that"),
+                    None => Err(
+                        "Unknown metadata name. Try frequency, note, source, tag, \
+                        article, case, plural or third person"
+                    ),
+                },
+            }
+        }
+
+        pub fn kind(&self) -> u8 {
+            match self {
+                Self::Frequency(_)
+                | Self::Note(_)
+                | Self::Source(_)
+                | Self::Tag(_)
+                | Self::Article(_)
+                | Self::KeepCase => METADATA_KIND,
+                Self::Related{ .. } => RELATION_KIND,
+                Self::Unknown{ kind, .. } => *kind,
+            }
+        }
+
+        pub fn key(&self) -> u8 {
+            match self {
+                Self::Frequency(_) => FREQUENCY_KEY,
+                Self::Note(_) => NOTE_KEY,
+                Self::Source(_) => SOURCE_KEY,
+                Self::Tag(_) => TAG_KEY,
+                Self::Article(_) => ARTICLE_KEY,
+                Self::KeepCase => CASE_KEY,
+                Self::Related{ relation, .. } => relation.key(),
+                Self::Unknown{ key, .. } => *key,
+            }
+        }
+
+        pub fn value(&self) -> Cow<'_, [u8]> {
+            match self {
+                Self::Frequency(frequency) => Cow::Owned(frequency.to_le_bytes().to_vec()),
+                Self::Note(text)
+                | Self::Source(text)
+                | Self::Tag(text) => Cow::Borrowed(text.as_bytes()),
+                Self::Article(article) => Cow::Borrowed(article.as_str().as_bytes()),
+                Self::KeepCase => Cow::Borrowed(KEEP_CASE.as_bytes()),
+                Self::Related{ index, .. } => Cow::Owned((*index as u32).to_le_bytes().to_vec()),
+                Self::Unknown{ value, .. } => Cow::Borrowed(value),
+            }
+        }
+    }
+}
+use extension::{Extension, Extensions};
+
+// Labelled Lexeme
+#[derive(Clone)]
+struct LL {
     lexeme: Lexeme,
-    flags: Flags
+    flags: Flags,
+    extensions: Vec<Extension>,
+}
+
+/// A lexeme with no extensions, for tests.
+#[cfg(test)]
+fn ll(lexeme: &str, flags: Flags) -> LL {
+    LL {
+        lexeme: Lexeme::from_input(lexeme).unwrap(),
+        flags,
+        extensions: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+fn adjective(lexeme: &str, category: AdjectiveOrderCategory) -> LL {
+    ll(lexeme, Flags::from(category) << ADJECTIVE_ORDER_SHIFT)
+}
+
+/// A directory of its own for each test, removed when dropped.
+#[cfg(test)]
+struct TempDir(PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("llledit-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Writes `lll` to a .lll file in the directory, and returns its
+    /// path.
+    fn lll(&self, lll: &[LL]) -> PathBuf {
+        let path = self.0.join("db.lll");
+        std::fs::write(&path, encode_lll(lll)).unwrap();
+        path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[derive(Debug)]
+enum ParseError {
+    Msg(ErrMsg),
+    UnsupportedVersion(u8),
+    /// `offset` is the byte offset, within the file, of the start of the
+    /// lexeme.
+    Lexeme{ offset: usize, error: lexeme::Error },
+}
+
+impl From<ErrMsg> for ParseError {
+    fn from(msg: ErrMsg) -> Self {
+        Self::Msg(msg)
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Msg(msg) => write!(f, "{msg}"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "File was an unsupported version of .lll format: {version}"
+            ),
+            Self::Lexeme{ offset, error } => write!(
+                f,
+                "The lexeme starting at byte {offset} was invalid: {error}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A Labelled Lexeme borrowed from the bytes of a .lll file.
+#[derive(Clone, Copy)]
+struct LLRef<'bytes> {
+    lexeme: &'bytes str,
+    flags: Flags,
+    extensions: Extensions<'bytes>,
+}
+
+impl<'bytes> LLRef<'bytes> {
+    /// The lexeme's extensions, parsed one at a time as they are needed.
+    fn parsed_extensions(&self) -> impl Iterator<Item = Extension> + 'bytes {
+        self.extensions.map(Extension::from)
+    }
+}
+
+impl TryFrom<LLRef<'_>> for LL {
+    type Error = lexeme::Error;
+
+    fn try_from(ll: LLRef<'_>) -> Result<Self, Self::Error> {
+        Ok(LL {
+            lexeme: Lexeme::from_disk(ll.lexeme.as_bytes())?,
+            flags: ll.flags,
+            extensions: ll.extensions.map(Extension::from).collect(),
+        })
+    }
+}
+
+/// An iterator over the blocks of a .lll file, which borrows the lexemes
+/// directly from the file's bytes, rather than copying them. Blocks with the
+/// FEF set are yielded as part of the lexeme they follow, and skipped if they
+/// do not follow one. Yields at most one error, after which it stops.
+struct Blocks<'bytes> {
+    bytes: &'bytes [u8],
+    i: usize,
+    version: Version,
+    complete_end: usize,
+}
+
+impl<'bytes> Blocks<'bytes> {
+    fn new(bytes: &'bytes [u8]) -> Result<Self, ParseError> {
+        if bytes.len() < V0_HEADER.len() {
+            return Err("File was not .lll format: Too short.".into());
+        }
+        if bytes[0] != V0_HEADER[0]
+        || bytes[1] != V0_HEADER[1]
+        || bytes[2] != V0_HEADER[2] {
+            return Err("File was not .lll format: Header wrong".into());
+        }
+
+        let version = Version::from_byte(bytes[3])
+            .ok_or(ParseError::UnsupportedVersion(bytes[3]))?;
+
+        Ok(Self {
+            bytes,
+            i: V0_HEADER.len(),
+            version,
+            complete_end: bytes.len(),
+        })
+    }
+
+    /// For blocks without a magic number in front of them, as in the
+    /// journal.
+    fn headerless(bytes: &'bytes [u8], version: Version) -> Self {
+        Self {
+            bytes,
+            i: 0,
+            version,
+            complete_end: bytes.len(),
+        }
+    }
+
+    fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Once the iterator is finished, the end of the last complete block.
+    /// That is the end of the input, unless the input ends partway through
+    /// a block, which we otherwise quietly ignore.
+    fn complete_end(&self) -> usize {
+        self.complete_end
+    }
+
+    /// Returns the end of the run of complete extension blocks starting at
+    /// `start`.
+    fn extensions_end(&self, start: usize) -> usize {
+        let mut end = start;
+        while let Some((true, len)) = self.version.read_length(&self.bytes[end..]) {
+            if len < self.version.block_header_length()
+            || end + len > self.bytes.len() {
+                // Leave it for `next` to report, or ignore if it was only
+                // partially written.
+                break
+            }
+            end += len;
+        }
+        end
+    }
+}
+
+impl<'bytes> Iterator for Blocks<'bytes> {
+    type Item = Result<LLRef<'bytes>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = self.bytes;
+        let version = self.version;
+
+        while self.i < bytes.len() {
+            let i = self.i;
+
+            let Some((fef, len)) = version.read_length(&bytes[i..]) else {
+                // A partially written length.
+                self.complete_end = i;
+                self.i = bytes.len();
+                break
+            };
+            if len < version.block_header_length() {
+                self.i = bytes.len();
+                return Some(Err("Table seems corrupted: Block length was invalid.".into()));
+            }
+
+            let block_end = i + len;
+            if block_end > bytes.len() {
+                self.complete_end = i;
+                self.i = bytes.len();
+                break
+            }
+
+            self.i = block_end;
+
+            if fef {
+                // Skip because there is no lexeme for this extension to
+                // apply to.
+                continue
+            }
+
+            let extensions_end = self.extensions_end(block_end);
+            self.i = extensions_end;
+
+            let res = parse_block_ref(&bytes[i..block_end], version)
+                .map(|ll| LLRef {
+                    extensions: Extensions::new(&bytes[block_end..extensions_end], version),
+                    ..ll
+                })
+                .map_err(|error| match error {
+                    ParseError::Lexeme{ offset, error } => ParseError::Lexeme{
+                        offset: i + offset,
+                        error,
+                    },
+                    other => other,
+                });
+            if res.is_err() {
+                self.i = bytes.len();
+            }
+            return Some(res);
+        }
+
+        None
+    }
+}
+
+/// Like `parse_lll`, but borrows the lexemes from `bytes`, rather than
+/// copying them, for the commands that only read the lll.
+fn parse_lll_refs(bytes: &[u8]) -> Result<Vec<LLRef<'_>>, ParseError> {
+    let mut output = Vec::with_capacity(bytes.len() / 16);
+
+    let start = bytes.as_ptr() as usize;
+    for ll in Blocks::new(bytes)? {
+        let ll = ll?;
+        lexeme::check(ll.lexeme).map_err(|error| ParseError::Lexeme{
+            offset: ll.lexeme.as_ptr() as usize - start,
+            error,
+        })?;
+        output.push(ll);
+    }
+
+    Ok(output)
+}
+
+fn parse_lll(bytes: &[u8]) -> Result<Vec<LL>, ParseError> {
+    parse_lll_with_version(bytes).map(|(lll, _, _)| lll)
+}
+
+/// Also returns the version of the file, and the end of its last complete
+/// block, as `Blocks::complete_end` does.
+fn parse_lll_with_version(bytes: &[u8]) -> Result<(Vec<LL>, Version, usize), ParseError> {
+    let mut output = Vec::with_capacity(bytes.len() / 16);
+
+    let start = bytes.as_ptr() as usize;
+    let mut blocks = Blocks::new(bytes)?;
+    let version = blocks.version();
+    for ll in &mut blocks {
+        let ll = ll?;
+        output.push(ll.try_into().map_err(|error| ParseError::Lexeme{
+            offset: ll.lexeme.as_ptr() as usize - start,
+            error,
+        })?);
+    }
+
+    Ok((output, version, blocks.complete_end()))
+}
+
+/// Parses a single, non-FEF, block, without any extensions. `block` should be
+/// exactly as long as the block's length field says it is. Offsets in errors
+/// are relative to the start of `block`.
+fn parse_block_ref(block: &[u8], version: Version) -> Result<LLRef<'_>, ParseError> {
+    let header_length = version.block_header_length();
+    if block.len() < header_length {
+        return Err("Table seems corrupted: Block length was invalid.".into());
+    }
+
+    let lexeme = std::str::from_utf8(&block[header_length..])
+        .map_err(|e| ParseError::Lexeme{
+            offset: header_length,
+            error: lexeme::Error::InvalidUtf8{ valid_up_to: e.valid_up_to() },
+        })?;
+
+    let flags_offset = version.flags_offset();
+    let flags = block[flags_offset] as Flags
+        | (block[flags_offset + 1] as Flags) << 8
+        | (block[flags_offset + 2] as Flags) << 16
+        ;
+
+    Ok(LLRef {
+        lexeme,
+        flags,
+        extensions: Extensions::new(&[], version),
+    })
+}
+
+mod coverage;
+mod generator;
+mod inflection;
+mod store;
+mod template;
+
+use store::{Change, Store};
+
+/// Ways of making lexemes that look the same to a person, also be the same to
+/// us.
+mod canonical {
+    use std::borrow::Cow;
+    use unicode_normalization::UnicodeNormalization;
+
+    #[derive(Clone, Copy, Debug)]
+    pub struct Options {
+        /// Whether to canonicalise input before storing it as a lexeme.
+        /// Comparisons always use canonicalised lexemes, regardless.
+        pub normalise_input: bool,
+        /// Whether comparisons should ignore case.
+        pub fold_case: bool,
+    }
+
+    impl Default for Options {
+        fn default() -> Self {
+            Self {
+                normalise_input: true,
+                fold_case: false,
+            }
+        }
+    }
+
+    /// NFC normalises `s`, trims it, and collapses any internal runs of
+    /// whitespace into a single space.
+    pub fn canonicalise(s: &str) -> String {
+        let mut output = String::with_capacity(s.len());
+        for word in s.split_whitespace() {
+            if !output.is_empty() {
+                output.push(' ');
+            }
+            output.extend(word.nfc());
+        }
+        output
+    }
+
+    impl Options {
+        /// Prepares input to be made into a lexeme.
+        pub fn input<'s>(&self, input: &'s str) -> Cow<'s, str> {
+            if self.normalise_input {
+                Cow::Owned(canonicalise(input))
+            } else {
+                Cow::Borrowed(input)
+            }
+        }
+
+        /// Two lexemes should be considered the same if their keys are equal.
+        pub fn comparison_key(&self, s: &str) -> String {
+            let canonical = canonicalise(s);
+            if self.fold_case {
+                // Lowercasing can produce non-NFC output in some cases.
+                canonical.to_lowercase().nfc().collect()
+            } else {
+                canonical
+            }
+        }
+    }
 }
 
-fn parse_lll(bytes: &[u8]) -> Result<Vec<LL>, ErrMsg> {
-    if bytes.len() < V0_HEADER.len() {
-        return Err("File was not .lll format: Too short.");
-    }
-    if bytes[0] != V0_HEADER[0]
-    || bytes[1] != V0_HEADER[1]
-    || bytes[2] != V0_HEADER[2] {
-        return Err("File was not .lll format: Header wrong");
+mod editor {
+    use super::*;
+    use std::borrow::Cow;
+
+    enum State {
+        Menu,
+        /// `ll` is `None` until a new lexeme has been typed.
+        AddChars{ ll: Option<LL> },
+        AddFlags{ ll: LL },
+        AddInflections{ ll: LL, suggestions: Vec<Suggestion> },
+        SelectEditIndex{ index: Option<usize> },
+        EditChars{ ll: LL, index: usize },
+        EditFlags{ ll: LL, index: usize },
+        EditMetadata{ ll: LL, index: usize },
+    }
+
+    pub enum Input {
+        /// A line of text, as typed by the user, possibly including the
+        /// trailing newline.
+        Line(String),
+        /// Commands for the flags states, already parsed. Ignored in other
+        /// states.
+        FlagsCommands(Box<[FlagsCommand]>),
+    }
+
+    /// Things the caller of `Editor::handle` should do, that the editor
+    /// cannot do itself.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Effect {
+        /// The lll has changed and should be written out.
+        Save(Box<[Change]>),
+        Quit,
+    }
+
+    /// Another form of a lexeme being added, which could be added along with
+    /// it.
+    pub struct Suggestion {
+        /// What this form is to the lexeme being added. A form can be more
+        /// than one thing, like "boots" being both the plural of the noun
+        /// "boot" and the third person form of the verb.
+        pub relations: Vec<extension::Relation>,
+        pub lexeme: Lexeme,
+        pub flags: Flags,
+        /// If the form is already in the lll, then it is only linked to.
+        pub existing: Option<usize>,
+    }
+
+    /// Everything needed to draw the current screen.
+    pub enum View<'editor> {
+        Menu{ err: &'editor str },
+        AddChars{ ll: Option<&'editor LL>, err: &'editor str },
+        AddFlags{ ll: &'editor LL, err: &'editor str },
+        AddInflections{
+            ll: &'editor LL,
+            suggestions: &'editor [Suggestion],
+            err: &'editor str,
+        },
+        SelectEditIndex{
+            selected: Option<(usize, &'editor LL)>,
+            /// For looking up related lexemes.
+            lll: &'editor [LL],
+            err: &'editor str,
+        },
+        EditChars{ ll: &'editor LL, prev: Option<&'editor LL>, err: &'editor str },
+        EditFlags{ ll: &'editor LL, prev: Option<&'editor LL>, err: &'editor str },
+        EditMetadata{ ll: &'editor LL, err: &'editor str },
+    }
+
+    /// The lexeme editing state machine, independent of any actual terminal.
+    pub struct Editor {
+        lll: Vec<LL>,
+        state: State,
+        err: Cow<'static, str>,
+        canonical: canonical::Options,
+        /// A shortened version of the last input, if it was too long.
+        truncation: Option<Lexeme>,
+    }
+
+    impl Editor {
+        pub fn new(lll: Vec<LL>, canonical: canonical::Options) -> Self {
+            Self {
+                lll,
+                state: State::Menu,
+                err: Cow::Borrowed(""),
+                canonical,
+                truncation: None,
+            }
+        }
+
+        /// Returns the index of a lexeme that should be considered the same as
+        /// `s`, other than the one at `except`, if there is one.
+        fn find(&self, s: &str, except: Option<usize>) -> Option<usize> {
+            let key = self.canonical.comparison_key(s);
+
+            self.lll.iter()
+                .enumerate()
+                .filter(|&(i, _)| Some(i) != except)
+                .find(|(_, ll)| self.canonical.comparison_key(ll.lexeme.as_str()) == key)
+                .map(|(i, _)| i)
+        }
+
+        /// If the lexeme would be too long, then a truncated version is
+        /// offered, which will be used if the next input is empty.
+        fn lexeme_from_input(
+            &mut self,
+            line: &str,
+            truncation: Option<Lexeme>,
+            except: Option<usize>,
+        ) -> Result<Lexeme, Cow<'static, str>> {
+            let input = self.canonical.input(line);
+
+            let res = match truncation {
+                Some(truncated) if input.trim().is_empty() => Ok(truncated),
+                _ => Lexeme::from_input(&input),
+            };
+
+            let lexeme = match res {
+                Ok(lexeme) => lexeme,
+                Err(e @ lexeme::Error::TooLong{ .. }) => {
+                    let truncated = Lexeme::from_input(lexeme::truncate(input.trim()));
+                    return Err(match truncated {
+                        Ok(truncated) => {
+                            let message = format!(
+                                "{e}\nPress enter to use this instead: {truncated:#}"
+                            );
+                            self.truncation = Some(truncated);
+                            Cow::Owned(message)
+                        }
+                        Err(_) => Cow::Owned(e.to_string()),
+                    });
+                }
+                Err(e) => return Err(Cow::Owned(e.to_string())),
+            };
+
+            match self.find(lexeme.as_str(), except) {
+                Some(i) => Err(Cow::Owned(format!(
+                    "{lexeme:#} is already in the lll, at index {i}. Edit that one instead."
+                ))),
+                None => Ok(lexeme),
+            }
+        }
+
+        /// The likely other forms of `ll`, going by its flags.
+        fn suggest_inflections(&self, ll: &LL) -> Vec<Suggestion> {
+            use extension::Relation;
+
+            let verb_flags = ll.flags & (INTRANSITIVE_VERB | TRANSITIVE_VERB);
+            type Inflect = fn(&str) -> String;
+
+            // The flag the base form has, how it relates to the other form,
+            // how to make the other form, and the other form's flags.
+            let forms: [(Flags, Relation, Inflect, Flags); 2] = [
+                (SINGULAR_NOUN, Relation::Plural, inflection::plural, PLURAL_NOUN),
+                (
+                    FIRST_PERSON_SINGULAR_VERB,
+                    Relation::ThirdPersonSingular,
+                    inflection::third_person_singular,
+                    THIRD_PERSON_SINGULAR_VERB | verb_flags,
+                ),
+            ];
+
+            let mut suggestions: Vec<Suggestion> = Vec::new();
+            for (base_flag, relation, inflect, flags) in forms {
+                if ll.flags & base_flag == 0 {
+                    continue
+                }
+
+                let inflected = inflect(ll.lexeme.as_str());
+                let Ok(lexeme) = Lexeme::from_input(&self.canonical.input(&inflected)) else {
+                    continue
+                };
+                let key = self.canonical.comparison_key(lexeme.as_str());
+                if key == self.canonical.comparison_key(ll.lexeme.as_str()) {
+                    // Like "sheep", which is its own plural.
+                    continue
+                }
+
+                let same_form = suggestions.iter_mut().find(|suggestion| {
+                    self.canonical.comparison_key(suggestion.lexeme.as_str()) == key
+                });
+                match same_form {
+                    Some(suggestion) => {
+                        suggestion.relations.push(relation);
+                        suggestion.flags |= flags;
+                    },
+                    None => suggestions.push(Suggestion {
+                        relations: vec![relation],
+                        existing: self.find(lexeme.as_str(), None),
+                        lexeme,
+                        flags,
+                    }),
+                }
+            }
+
+            suggestions
+        }
+
+        /// Adds `ll`, along with any of its other forms that are not already
+        /// in the lll, linking it to all of them.
+        fn add(&mut self, mut ll: LL, accepted: Vec<Suggestion>) -> Effect {
+            let index = self.lll.len();
+            let mut forms = Vec::with_capacity(accepted.len());
+
+            for suggestion in accepted {
+                let form_index = match suggestion.existing {
+                    Some(existing) => existing,
+                    None => {
+                        forms.push(LL {
+                            lexeme: suggestion.lexeme,
+                            flags: suggestion.flags,
+                            extensions: Vec::new(),
+                        });
+                        index + forms.len()
+                    }
+                };
+                for relation in suggestion.relations {
+                    ll.extensions.push(Extension::Related{ relation, index: form_index });
+                }
+            }
+
+            let mut changes = Vec::with_capacity(1 + forms.len());
+            for ll in std::iter::once(ll).chain(forms) {
+                changes.push(Change::Added{ index: self.lll.len() });
+                self.lll.push(ll);
+            }
+
+            Effect::Save(changes.into())
+        }
+
+        pub fn lll(&self) -> &[LL] {
+            &self.lll
+        }
+
+        /// The error from the last input, if there was one.
+        pub fn err(&self) -> Option<&str> {
+            if self.err.is_empty() {
+                None
+            } else {
+                Some(&self.err)
+            }
+        }
+
+        pub fn view(&self) -> View<'_> {
+            let err = &*self.err;
+            match self.state {
+                State::Menu => View::Menu{ err },
+                State::AddChars{ ref ll } => View::AddChars{ ll: ll.as_ref(), err },
+                State::AddFlags{ ref ll } => View::AddFlags{ ll, err },
+                State::AddInflections{ ref ll, ref suggestions } => View::AddInflections{
+                    ll,
+                    suggestions,
+                    err,
+                },
+                State::SelectEditIndex{ index } => View::SelectEditIndex{
+                    selected: index.and_then(|i| self.lll.get(i).map(|ll| (i, ll))),
+                    lll: &self.lll,
+                    err,
+                },
+                State::EditChars{ ref ll, index } => View::EditChars{
+                    ll,
+                    prev: self.lll.get(index),
+                    err,
+                },
+                State::EditFlags{ ref ll, index } => View::EditFlags{
+                    ll,
+                    prev: self.lll.get(index),
+                    err,
+                },
+                State::EditMetadata{ ref ll, .. } => View::EditMetadata{ ll, err },
+            }
+        }
+
+        pub fn handle(&mut self, input: Input) -> Option<Effect> {
+            let mut effect = None;
+
+            // Errors only describe the input that caused them.
+            self.err = Cow::Borrowed("");
+            // Likewise, truncations are only offered for the next input.
+            let truncation = self.truncation.take();
+
+            let line = match input {
+                Input::Line(ref line) => line.as_str(),
+                Input::FlagsCommands(_) => "",
+            };
+
+            // Take the state so we can move out of it. It is always replaced
+            // below.
+            let state = std::mem::replace(&mut self.state, State::Menu);
+
+            self.state = match state {
+                State::Menu => {
+                    match line.chars().next() {
+                        Some('q') => {
+                            effect = Some(Effect::Quit);
+                            state
+                        },
+                        Some('a') => {
+                            State::AddChars{ ll: None }
+                        },
+                        Some('e') => {
+                            State::SelectEditIndex{ index: None }
+                        },
+                        None => {
+                            self.err = Cow::Borrowed("Type a letter to select an option");
+                            state
+                        },
+                        _ => {
+                            self.err = Cow::Borrowed("???");
+                            state
+                        }
+                    }
+                }
+                State::AddChars{ ll } => {
+                    match self.lexeme_from_input(line, truncation, None) {
+                        Ok(lexeme) => {
+                            let ll = match ll {
+                                Some(ll) => LL { lexeme, ..ll },
+                                None => LL { lexeme, flags: 0, extensions: Vec::new() },
+                            };
+                            State::AddFlags{ ll }
+                        },
+                        Err(e) => {
+                            self.err = e;
+                            State::AddChars{ ll }
+                        }
+                    }
+                }
+                State::AddFlags{ mut ll } => {
+                    match flags_switch(&mut ll, &input) {
+                        Ok(switch) => {
+                            match switch {
+                                StateSwitch::Stay => {
+                                    State::AddFlags{ ll }
+                                },
+                                StateSwitch::EditLexeme => {
+                                    State::AddChars{ ll: Some(ll) }
+                                },
+                                StateSwitch::Finished => {
+                                    let suggestions = self.suggest_inflections(&ll);
+                                    if suggestions.is_empty() {
+                                        effect = Some(self.add(ll, Vec::new()));
+                                        State::Menu
+                                    } else {
+                                        State::AddInflections{ ll, suggestions }
+                                    }
+                                },
+                            }
+                        },
+                        Err(e) => {
+                            self.err = Cow::Borrowed(e);
+                            State::AddFlags{ ll }
+                        }
+                    }
+                }
+                State::AddInflections{ ll, mut suggestions } => {
+                    let command = line.trim();
+                    let accepted = match command {
+                        "" | "y" => Ok(suggestions),
+                        "n" => Ok(Vec::new()),
+                        _ => {
+                            let numbers: Result<Vec<usize>, _> = command
+                                .split_whitespace()
+                                .map(str::parse)
+                                .collect();
+                            match numbers {
+                                Ok(numbers) if numbers.iter().all(|&i| i < suggestions.len()) => {
+                                    let mut i = 0;
+                                    suggestions.retain(|_| {
+                                        i += 1;
+                                        numbers.contains(&(i - 1))
+                                    });
+                                    Ok(suggestions)
+                                },
+                                _ => Err(suggestions),
+                            }
+                        }
+                    };
+
+                    match accepted {
+                        Ok(accepted) => {
+                            effect = Some(self.add(ll, accepted));
+                            State::Menu
+                        },
+                        Err(suggestions) => {
+                            self.err = Cow::Borrowed(
+                                "Type the numbers of the forms to add, separated by spaces"
+                            );
+                            State::AddInflections{ ll, suggestions }
+                        }
+                    }
+                }
+                State::SelectEditIndex{ index } => {
+                    // Commands are a single letter, so that lexemes starting
+                    // with those letters can still be searched for.
+                    let mut chars = line.trim().chars();
+                    let command = match (chars.next(), chars.next()) {
+                        (Some(c), None) => Some(c),
+                        _ => None,
+                    };
+
+                    match (index, command) {
+                        (_, Some('q')) => {
+                            State::Menu
+                        },
+                        (Some(i), Some('e')) => {
+                            match self.lll.get(i) {
+                                Some(ll) => {
+                                    State::EditChars{ ll: ll.clone(), index: i }
+                                }
+                                None => {
+                                    self.err = Cow::Borrowed("No lexeme at that index");
+                                    State::SelectEditIndex{ index: Some(i) }
+                                }
+                            }
+                        },
+                        (Some(i), Some('f')) => {
+                            match self.lll.get(i) {
+                                Some(ll) => {
+                                    State::EditFlags{ ll: ll.clone(), index: i }
+                                }
+                                None => {
+                                    self.err = Cow::Borrowed("No lexeme at that index");
+                                    State::SelectEditIndex{ index: Some(i) }
+                                }
+                            }
+                        },
+                        (Some(i), Some('m')) => {
+                            match self.lll.get(i) {
+                                Some(ll) => {
+                                    State::EditMetadata{ ll: ll.clone(), index: i }
+                                }
+                                None => {
+                                    self.err = Cow::Borrowed("No lexeme at that index");
+                                    State::SelectEditIndex{ index: Some(i) }
+                                }
+                            }
+                        },
+                        _ => {
+                            // TODO? allow jumping to add a new lexeme from here?
+                            let query = line.trim();
+                            match query.parse::<usize>() {
+                                Ok(i) => {
+                                    State::SelectEditIndex{ index: Some(i) }
+                                },
+                                Err(_) if query.is_empty() => {
+                                    self.err = Cow::Borrowed("Could not parse index");
+                                    State::SelectEditIndex{ index }
+                                },
+                                Err(_) => match self.find(query, None) {
+                                    Some(i) => State::SelectEditIndex{ index: Some(i) },
+                                    None => {
+                                        self.err = Cow::Owned(format!(
+                                            "No lexeme matches \"{query}\""
+                                        ));
+                                        State::SelectEditIndex{ index }
+                                    }
+                                },
+                            }
+                        }
+                    }
+                }
+                State::EditChars{ mut ll, index } => {
+                    // TODO? Implement actual piecewise editing,
+                    // instead of just replacing?
+                    match self.lexeme_from_input(line, truncation, Some(index)) {
+                        Ok(lexeme) => {
+                            ll.lexeme = lexeme;
+                            State::EditFlags{ ll, index }
+                        },
+                        Err(e) => {
+                            self.err = e;
+                            State::EditChars{ ll, index }
+                        }
+                    }
+                }
+                State::EditFlags{ mut ll, index } => {
+                    match flags_switch(&mut ll, &input) {
+                        Ok(switch) => {
+                            match switch {
+                                StateSwitch::Stay => {
+                                    State::EditFlags{ ll, index }
+                                },
+                                StateSwitch::EditLexeme => {
+                                    State::EditChars{ ll, index }
+                                },
+                                StateSwitch::Finished => {
+                                    let change = if index < self.lll.len() {
+                                        self.lll[index] = ll;
+                                        Change::Replaced{ index }
+                                    } else {
+                                        // TODO? Break instead? check for a duplicate?
+                                        self.lll.push(ll);
+                                        Change::Added{ index: self.lll.len() - 1 }
+                                    };
+
+                                    effect = Some(Effect::Save(Box::new([change])));
+
+                                    State::Menu
+                                },
+                            }
+                        },
+                        Err(e) => {
+                            self.err = Cow::Borrowed(e);
+                            State::EditFlags{ ll, index }
+                        }
+                    }
+                }
+                State::EditMetadata{ mut ll, index } => {
+                    let command = line.trim();
+                    match command {
+                        "q" => State::SelectEditIndex{ index: Some(index) },
+                        "f" => {
+                            match self.lll.get_mut(index) {
+                                Some(old) => {
+                                    *old = ll;
+                                    effect = Some(Effect::Save(Box::new([
+                                        Change::Replaced{ index },
+                                    ])));
+                                }
+                                None => {
+                                    self.err = Cow::Borrowed("No lexeme at that index");
+                                }
+                            }
+                            State::Menu
+                        },
+                        _ => {
+                            if let Some(number) = command.strip_prefix('-') {
+                                match number.trim().parse::<usize>() {
+                                    Ok(i) if i < ll.extensions.len() => {
+                                        ll.extensions.remove(i);
+                                    },
+                                    _ => {
+                                        self.err = Cow::Borrowed("No metadata at that index");
+                                    }
+                                }
+                            } else {
+                                let lookup = |s: &str| match s.parse::<usize>() {
+                                    Ok(i) => Some(i).filter(|&i| i < self.lll.len()),
+                                    Err(_) => self.find(s, None),
+                                };
+                                match Extension::from_input(command, lookup) {
+                                    Ok(extension) => ll.extensions.push(extension),
+                                    Err(e) => self.err = Cow::Borrowed(e),
+                                }
+                            }
+                            State::EditMetadata{ ll, index }
+                        }
+                    }
+                }
+            };
+
+            effect
+        }
+    }
+
+    fn flags_switch(ll: &mut LL, input: &Input) -> Result<StateSwitch, ErrMsg> {
+        match input {
+            Input::Line(line) => parse_flags_commands(line)
+                .map(|commands| handle_commands(ll, &commands)),
+            Input::FlagsCommands(commands) => Ok(handle_commands(ll, commands)),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use extension::Relation;
+
+        /// Feeds `lines` to `editor`, as if they were typed, returning the
+        /// effect of the last one.
+        fn type_lines(editor: &mut Editor, lines: &[&str]) -> Option<Effect> {
+            let mut effect = None;
+            for line in lines {
+                effect = editor.handle(Input::Line(format!("{line}\n")));
+            }
+            effect
+        }
+
+        fn new_editor(lll: Vec<LL>) -> Editor {
+            Editor::new(lll, canonical::Options::default())
+        }
+
+        #[test]
+        fn adding_a_noun_adds_its_plural_too() {
+            let mut editor = new_editor(Vec::new());
+
+            assert_eq!(type_lines(&mut editor, &["a", "cow", "s0,f"]), None);
+            match editor.view() {
+                View::AddInflections{ ll, suggestions, err } => {
+                    assert_eq!(ll.lexeme.as_str(), "cow");
+                    assert_eq!(suggestions.len(), 1);
+                    assert_eq!(suggestions[0].lexeme.as_str(), "cows");
+                    assert_eq!(suggestions[0].flags, PLURAL_NOUN);
+                    assert_eq!(suggestions[0].existing, None);
+                    assert_eq!(err, "");
+                }
+                _ => panic!("expected to be offered the plural"),
+            }
+
+            assert_eq!(
+                type_lines(&mut editor, &["y"]),
+                Some(Effect::Save(Box::new([
+                    Change::Added{ index: 0 },
+                    Change::Added{ index: 1 },
+                ]))),
+            );
+            assert!(matches!(editor.view(), View::Menu{ err: "" }));
+
+            let lll = editor.lll();
+            assert_eq!(lll.len(), 2);
+            assert_eq!(lll[0].flags, SINGULAR_NOUN);
+            assert_eq!(lll[1].lexeme.as_str(), "cows");
+            assert_eq!(extension::related(lll, 0, Relation::Plural), Some(1));
+        }
+
+        #[test]
+        fn declining_the_other_forms_only_adds_the_lexeme() {
+            let mut editor = new_editor(Vec::new());
+
+            assert_eq!(
+                type_lines(&mut editor, &["a", "cow", "s0,f", "n"]),
+                Some(Effect::Save(Box::new([Change::Added{ index: 0 }]))),
+            );
+            assert_eq!(editor.lll().len(), 1);
+            assert!(editor.lll()[0].extensions.is_empty());
+        }
+
+        #[test]
+        fn adding_links_to_forms_that_are_already_there() {
+            let mut editor = new_editor(vec![ll("cows", PLURAL_NOUN)]);
+
+            assert_eq!(
+                type_lines(&mut editor, &["a", "cow", "s0,f", "y"]),
+                Some(Effect::Save(Box::new([Change::Added{ index: 1 }]))),
+            );
+            assert_eq!(extension::related(editor.lll(), 1, Relation::Plural), Some(0));
+        }
+
+        #[test]
+        fn duplicates_are_rejected() {
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN)]);
+
+            assert_eq!(type_lines(&mut editor, &["a", "  cow "]), None);
+            match editor.view() {
+                View::AddChars{ err, .. } => assert_eq!(
+                    err,
+                    "\"cow\" is already in the lll, at index 0. Edit that one instead.",
+                ),
+                _ => panic!("expected to stay on the lexeme"),
+            }
+
+            // Another lexeme cannot be renamed to it either.
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN)]);
+            assert_eq!(type_lines(&mut editor, &["e", "1", "e", "cow"]), None);
+            assert!(matches!(editor.view(), View::EditChars{ err, .. } if !err.is_empty()));
+
+            // But a lexeme can be "renamed" to itself.
+            assert_eq!(type_lines(&mut editor, &["hat"]), None);
+            assert!(matches!(editor.view(), View::EditFlags{ err: "", .. }));
+        }
+
+        fn is_duplicate(editor: &Editor) -> bool {
+            matches!(
+                editor.view(),
+                View::AddChars{ err, .. } if err.contains("is already in the lll, at index 0")
+            )
+        }
+
+        #[test]
+        fn differently_encoded_duplicates_are_rejected() {
+            let nfc = "caf\u{e9}";
+            let nfd = "cafe\u{301}";
+
+            let mut editor = new_editor(vec![ll(nfc, MASS_NOUN)]);
+            type_lines(&mut editor, &["a", nfd]);
+            assert!(is_duplicate(&editor));
+
+            // Even when input is stored as it was typed.
+            let raw = canonical::Options{ normalise_input: false, ..Default::default() };
+            let mut editor = Editor::new(vec![ll(nfc, MASS_NOUN)], raw);
+            type_lines(&mut editor, &["a", nfd]);
+            assert!(is_duplicate(&editor));
+
+            let mut editor = new_editor(Vec::new());
+            type_lines(&mut editor, &["a", nfd, "s2,f"]);
+            assert_eq!(editor.lll()[0].lexeme.as_str(), nfc);
+        }
+
+        #[test]
+        fn duplicates_that_differ_in_case_are_only_rejected_when_folding_case() {
+            let mut editor = new_editor(vec![ll("Cow", SINGULAR_NOUN)]);
+            type_lines(&mut editor, &["a", "cow"]);
+            assert!(matches!(editor.view(), View::AddFlags{ err: "", .. }));
+
+            let fold_case = canonical::Options{ fold_case: true, ..Default::default() };
+            let mut editor = Editor::new(vec![ll("Cow", SINGULAR_NOUN)], fold_case);
+            type_lines(&mut editor, &["a", "cow"]);
+            assert!(is_duplicate(&editor));
+        }
+
+        #[test]
+        fn editing_flags_replaces_the_lexeme() {
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN)]);
+
+            assert_eq!(type_lines(&mut editor, &["e", "0", "f", "s2"]), None);
+            match editor.view() {
+                View::EditFlags{ ll, prev, err } => {
+                    assert_eq!(ll.flags, SINGULAR_NOUN | MASS_NOUN);
+                    assert_eq!(prev.map(|prev| prev.flags), Some(SINGULAR_NOUN));
+                    assert_eq!(err, "");
+                }
+                _ => panic!("expected to still be editing flags"),
+            }
+            // Nothing changes until the flags are finished.
+            assert_eq!(editor.lll()[0].flags, SINGULAR_NOUN);
+
+            assert_eq!(
+                type_lines(&mut editor, &["u0,f"]),
+                Some(Effect::Save(Box::new([Change::Replaced{ index: 0 }]))),
+            );
+            assert_eq!(editor.lll()[0].flags, MASS_NOUN);
+        }
+
+        #[test]
+        fn editing_metadata_replaces_the_lexeme_when_finished() {
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN), ll("cows", PLURAL_NOUN)]);
+
+            assert_eq!(
+                type_lines(&mut editor, &[
+                    "e", "0", "m", "note: moos", "frequency: 5", "plural: cows", "-0",
+                ]),
+                None,
+            );
+            match editor.view() {
+                View::EditMetadata{ ll, err } => {
+                    assert_eq!(ll.extensions, [
+                        Extension::Frequency(5),
+                        Extension::Related{ relation: Relation::Plural, index: 1 },
+                    ]);
+                    assert_eq!(err, "");
+                }
+                _ => panic!("expected to still be editing metadata"),
+            }
+            assert!(editor.lll()[0].extensions.is_empty());
+
+            assert_eq!(
+                type_lines(&mut editor, &["f"]),
+                Some(Effect::Save(Box::new([Change::Replaced{ index: 0 }]))),
+            );
+            assert_eq!(editor.lll()[0].extensions.len(), 2);
+        }
+
+        #[test]
+        fn going_back_from_metadata_discards_it() {
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN)]);
+
+            assert_eq!(type_lines(&mut editor, &["e", "0", "m", "note: moos", "-5"]), None);
+            assert!(matches!(editor.view(), View::EditMetadata{ err, .. } if !err.is_empty()));
+
+            assert_eq!(type_lines(&mut editor, &["q"]), None);
+            assert!(matches!(editor.view(), View::SelectEditIndex{ selected: Some((0, _)), .. }));
+            assert!(editor.lll()[0].extensions.is_empty());
+        }
+
+        #[test]
+        fn too_long_lexemes_are_offered_truncated() {
+            let mut editor = new_editor(Vec::new());
+            let long = "x".repeat(usize::from(lexeme::MAX_LENGTH) + 10);
+
+            assert_eq!(type_lines(&mut editor, &["a", &long]), None);
+            match editor.view() {
+                View::AddChars{ err, .. } => {
+                    assert!(err.contains("Press enter to use this instead"));
+                }
+                _ => panic!("expected to stay on the lexeme"),
+            }
+
+            assert_eq!(type_lines(&mut editor, &[""]), None);
+            match editor.view() {
+                View::AddFlags{ ll, err } => {
+                    assert_eq!(ll.lexeme.len(), lexeme::MAX_LENGTH);
+                    assert_eq!(err, "");
+                }
+                _ => panic!("expected the truncated lexeme to be used"),
+            }
+        }
+
+        #[test]
+        fn truncations_are_only_offered_for_the_next_input() {
+            let mut editor = new_editor(vec![ll("cow", SINGULAR_NOUN)]);
+            let long = "x".repeat(usize::from(lexeme::MAX_LENGTH) + 10);
+
+            assert_eq!(type_lines(&mut editor, &["a", &long, "cow", ""]), None);
+            match editor.view() {
+                View::AddChars{ err, .. } => assert_eq!(err, "Lexemes cannot be empty!"),
+                _ => panic!("expected the truncation to have been forgotten"),
+            }
+        }
+
+        #[test]
+        fn q_quits_from_the_menu() {
+            let mut editor = new_editor(Vec::new());
+            assert_eq!(type_lines(&mut editor, &["q"]), Some(Effect::Quit));
+        }
     }
+}
+use editor::{Editor, Effect, Input, View};
 
-    if bytes[3] != 0 {
-        if bytes[3] == 1 {
-            return Err("File was an unsupported version of .lll format: 1");
-        } else if bytes[3] == 2 {
-            return Err("File was an unsupported version of .lll format: 2");
-        } else {
-            return Err("File was an unsupported version of .lll format: >2");
+fn render(p: &Printer, view: &View) {
+    p.clear();
+    p.move_home();
+
+    match *view {
+        View::Menu{ err } => {
+            writeln!(p, "a) Add a lexeme");
+            writeln!(p, "e) Edit a lexeme");
+            writeln!(p, "q then enter to quit");
+            writeln!(p, "{err}");
+        }
+        View::AddChars{ ll, err } => {
+            writeln!(p, "Add a lexeme");
+            writeln!(p);
+            writeln!(p, "{err}");
+            match ll {
+                Some(ll) => write!(p, ">{}", ll.lexeme),
+                None => write!(p, ">"),
+            };
+        }
+        View::AddFlags{ ll, err } => {
+            writeln!(p, "Add flags to");
+            writeln!(p, "{:#}", ll.lexeme);
+            writeln!(p, ">{}", flag_display::Named(ll.flags));
+            print_flags_commands_help(p);
+            writeln!(p, "{err}");
+        }
+        View::AddInflections{ ll, suggestions, err } => {
+            writeln!(p, "Add other forms of");
+            writeln!(p, "{:#}", ll.lexeme);
+            writeln!(p);
+            for (i, suggestion) in suggestions.iter().enumerate() {
+                let names: Vec<&str> = suggestion.relations.iter()
+                    .map(|relation| relation.name())
+                    .collect();
+                write!(p, "{i}) {}: {:#} ", names.join(", "), suggestion.lexeme);
+                match suggestion.existing {
+                    Some(existing) => writeln!(p, "(already at @{existing}, so only linked)"),
+                    None => writeln!(p, "{}", flag_display::Named(suggestion.flags)),
+                }
+            }
+            writeln!(p);
+            writeln!(p, "y) or enter) add all of these n) add none of these");
+            writeln!(p, "N M ...) add only the numbered ones");
+            writeln!(p, "{err}");
+            write!(p, ">");
+        }
+        View::SelectEditIndex{ selected, lll, err } => {
+            writeln!(p, "Select a lexeme");
+            writeln!(p, "Enter an index or a lexeme to search for, or");
+            writeln!(p, "q) go back to the menu");
+            match selected {
+                Some((i, ll)) => {
+                    writeln!(p, "e) edit this lexeme f) edit this lexeme's flags m) edit this lexeme's metadata");
+                    writeln!(p);
+                    writeln!(p, "{err}");
+                    writeln!(p, "@{}", i);
+                    // TODO print the surrounding lexemes in the lll
+                    writeln!(p, "{:#}", ll.lexeme);
+                    writeln!(p, "{}", flag_display::Named(ll.flags));
+                    for extension in &ll.extensions {
+                        write!(p, "{extension}");
+                        if let Extension::Related{ index, .. } = *extension {
+                            if let Some(related) = lll.get(index) {
+                                write!(p, " {:#}", related.lexeme);
+                            }
+                        }
+                        writeln!(p);
+                    }
+                    for relation in extension::Relation::ALL {
+                        if let Some(base) = extension::related_from(lll, i, relation) {
+                            writeln!(
+                                p,
+                                "{}: @{base} {:#}",
+                                relation.inverse_name(),
+                                lll[base].lexeme,
+                            );
+                        }
+                    }
+                },
+                None => {
+                    writeln!(p);
+                    writeln!(p, "{err}");
+                    write!(p, ">");
+                }
+            }
+        }
+        View::EditChars{ ll, prev, err } => {
+            writeln!(p, "Edit a lexeme");
+            writeln!(p);
+            writeln!(p, "{err}");
+            if let Some(prev) = prev {
+                writeln!(p, "{:#}", prev.lexeme);
+                writeln!(p);
+            }
+            write!(p, ">{}", ll.lexeme);
+        }
+        View::EditFlags{ ll, prev, err } => {
+            writeln!(p, "Edit flags for");
+            writeln!(p, "{:#}", ll.lexeme);
+            if let Some(prev) = prev {
+                writeln!(p, "{}", flag_display::Named(prev.flags));
+                writeln!(
+                    p,
+                    "{}",
+                    flag_display::Diff{ old: prev.flags, new: ll.flags }
+                );
+                writeln!(p);
+            }
+            writeln!(p, ">{}", flag_display::Named(ll.flags));
+            print_flags_commands_help(p);
+            writeln!(p, "{err}");
+        }
+        View::EditMetadata{ ll, err } => {
+            writeln!(p, "Edit metadata for");
+            writeln!(p, "{:#}", ll.lexeme);
+            writeln!(p);
+            for (i, extension) in ll.extensions.iter().enumerate() {
+                writeln!(p, "{i}) {extension}");
+            }
+            writeln!(p);
+            writeln!(p, "NAME: VALUE) add metadata, where NAME is frequency, note, source, tag, article,");
+            writeln!(p, "             or case, whose only value is keep");
+            writeln!(p, "FORM: LEXEME) link to another form, where FORM is plural or third person,");
+            writeln!(p, "              and LEXEME is that form, or its index");
+            writeln!(p, "-N) remove metadata N");
+            writeln!(p, "f) finished editing metadata q) go back without saving");
+            writeln!(p, "{err}");
+            write!(p, ">");
         }
     }
 
-    const BLOCK_HEADER_LENGTH: u8 = 4;
+    p.flush();
+}
 
-    let mut output = Vec::with_capacity(bytes.len() / 16);
+const USAGE: &str = "Usage: llledit [--record JOURNAL | --replay JOURNAL] [--raw-input] [--fold-case] [DB]
+       llledit coverage [--fold-case] [DB]
+       llledit templates [DB]
+       llledit generate [--template NAME] [--seed N] [--count N] [--title-case]
+                        [--avoid-repeats FRACTION] [DB]
+
+coverage       List the singular nouns without plurals, first person verbs
+               without third person forms, and verbs without transitivity
+               flags, instead of editing.
+templates      List the templates that cannot be filled in from DB, and the
+               flags needed to fix them, instead of editing. Exits with
+               status 1 if there are any.
+generate       Print snowclones from DB, instead of editing. Lexemes with a
+               higher frequency, (set with m in the editor,) come up more
+               often.
+--template     Only use the template called NAME, as listed by templates.
+--seed         Generate the same snowclones as last time this seed was used.
+--count        Print N snowclones, one per line. The default is 1.
+--title-case   Capitalise words like in a headline, rather than only the
+               first word of each sentence.
+--avoid-repeats
+               Do not repeat a lexeme until FRACTION of the others that could
+               fill the same kind of slot have come up. From 0, to allow
+               repeats, to 1, to use them all first. The default is 0.5.
+
+--raw-input    Store lexemes as typed, rather than NFC normalised with
+               whitespace collapsed.
+--fold-case    Ignore case when searching and checking for duplicates.";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Edit,
+    Coverage,
+    Templates,
+    Generate,
+}
 
-    let mut i = V0_HEADER.len();
-    while i < bytes.len() {
-        let len = bytes[i] & 0x7F;
-        if len < BLOCK_HEADER_LENGTH {
-            return Err("Table seems corrupted: Block length was invalid.");
-        }
+struct Args {
+    command: Command,
+    db_path: PathBuf,
+    /// Where to write each line of input to, if anywhere.
+    record_path: Option<PathBuf>,
+    /// Where to read lines of input from, instead of the terminal, if anywhere.
+    replay_path: Option<PathBuf>,
+    canonical: canonical::Options,
+    /// Which template to generate from. Any of them, if `None`.
+    template_name: Option<String>,
+    seed: Option<u64>,
+    count: usize,
+    casing: generator::Casing,
+    avoid_repeats: f64,
+}
 
-        let block_end = i + usize::from(len);
-        if block_end > bytes.len() {
-            break
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    args.next(); // exe name
+
+    let mut args = args.peekable();
+    let command = match args.peek().map(String::as_str) {
+        Some("coverage") => {
+            args.next();
+            Command::Coverage
+        }
+        Some("templates") => {
+            args.next();
+            Command::Templates
         }
+        Some("generate") => {
+            args.next();
+            Command::Generate
+        }
+        _ => Command::Edit,
+    };
 
-        let fef = (bytes[i] & 0x80) == 0x80;
+    let mut db_path = None;
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut canonical = canonical::Options::default();
+    let mut template_name = None;
+    let mut seed = None;
+    let mut count = None;
+    let mut casing = None;
+    let mut avoid_repeats = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--template" => {
+                template_name = Some(
+                    args.next().ok_or_else(|| format!("{arg} needs a template name\n{USAGE}"))?
+                );
+            }
+            "--title-case" => casing = Some(generator::Casing::Title),
+            "--avoid-repeats" => {
+                avoid_repeats = Some(
+                    args.next()
+                        .and_then(|n| n.parse().ok())
+                        .filter(|fraction| (0.0..=1.0).contains(fraction))
+                        .ok_or_else(|| format!("{arg} needs a number from 0 to 1\n{USAGE}"))?
+                );
+            }
+            "--seed" | "--count" => {
+                let number = args.next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or_else(|| format!("{arg} needs a whole number\n{USAGE}"))?;
 
-        if fef {
-            // Skip because we don't know what the FEF does yet.
-            i = block_end;
-            continue
+                if arg == "--seed" {
+                    seed = Some(number);
+                } else {
+                    count = Some(usize::try_from(number).unwrap_or(usize::MAX));
+                }
+            }
+            "--raw-input" => canonical.normalise_input = false,
+            "--fold-case" => canonical.fold_case = true,
+            "--record" | "--replay" => {
+                let path = args.next()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| format!("{arg} needs a journal path\n{USAGE}"))?;
+
+                if arg == "--record" {
+                    record_path = Some(path);
+                } else {
+                    replay_path = Some(path);
+                }
+            }
+            "--help" | "-h" => return Err(USAGE.to_owned()),
+            _ if db_path.is_none() => {
+                db_path = Some(PathBuf::from(arg));
+            }
+            _ => return Err(format!("Unexpected argument: {arg}\n{USAGE}")),
         }
+    }
 
-        let lexeme = (&bytes[
-            i + usize::from(BLOCK_HEADER_LENGTH)..block_end
-        ]).try_into()?;
+    if record_path.is_some() && replay_path.is_some() {
+        return Err(format!("Cannot both record and replay at once\n{USAGE}"));
+    }
 
-        let flags = bytes[i + 1] as Flags
-            | (bytes[i + 2] as Flags) << 8
-            | (bytes[i + 3] as Flags) << 16
-            ;
+    if command != Command::Edit && (record_path.is_some() || replay_path.is_some()) {
+        return Err(format!("Can only record or replay while editing\n{USAGE}"));
+    }
 
-        output.push(LL {
-            lexeme,
-            flags
-        });
+    if command != Command::Generate
+    && (
+        template_name.is_some()
+        || seed.is_some()
+        || count.is_some()
+        || casing.is_some()
+        || avoid_repeats.is_some()
+    ) {
+        return Err(format!(
+            "--template, --seed, --count, --title-case and --avoid-repeats are only for \
+            generate\n{USAGE}"
+        ));
+    }
+
+    Ok(Args {
+        command,
+        db_path: db_path.unwrap_or_else(|| PathBuf::from("db.lll")),
+        record_path,
+        replay_path,
+        canonical,
+        template_name,
+        seed,
+        count: count.unwrap_or(1),
+        casing: casing.unwrap_or_default(),
+        avoid_repeats: avoid_repeats.unwrap_or(generator::DEFAULT_AVOID_REPEATS),
+    })
+}
+
+/// Applies each line in the journal at `replay_path` to the lll, in order,
+/// exactly as if they had been typed in, without drawing anything.
+/// What `input` is recorded as, for `replay` to read back.
+fn recorded_line(input: &Input) -> String {
+    let mut line = match input {
+        Input::Line(line) => line.clone(),
+        Input::FlagsCommands(commands) => flags_commands_to_line(commands),
+    };
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+    line
+}
+
+fn replay(
+    store: &mut Store,
+    lll: Vec<LL>,
+    replay_path: &PathBuf,
+    canonical: canonical::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let journal = std::fs::read_to_string(replay_path)
+        .map_err(|e| format!("{}: {e}", replay_path.to_string_lossy()))?;
+
+    let mut editor = Editor::new(lll, canonical);
 
-        i = block_end;
+    // Keep the newlines, since that is what we would get from the terminal.
+    for (i, line) in journal.split_inclusive('\n').enumerate() {
+        let effect = editor.handle(Input::Line(line.to_owned()));
+
+        if let Some(err) = editor.err() {
+            eprintln!("{}:{}: {err}", replay_path.to_string_lossy(), i + 1);
+        }
+
+        match effect {
+            Some(Effect::Save(changes)) => store.save(editor.lll(), &changes)?,
+            Some(Effect::Quit) => break,
+            None => {}
+        }
     }
 
-    Ok(output)
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut file = {
-        let mut args = std::env::args();
-        args.next(); // exe name
-
-        let path = args.next()
-            .map(PathBuf::from)
-            .unwrap_or_else(|| PathBuf::from("db.lll"));
-
-        std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(&path)
-            .map_err(|e| format!("{}: {e}", path.to_string_lossy()))?
+    let args = match parse_args(std::env::args()) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            std::process::exit(2);
+        }
     };
 
-    let p = match enable_ansi_support::enable_ansi_support() {
-        Ok(()) => Printer::ansi(),
-        Err(e) => {
-            eprintln!("{e}");
-            Printer::default()
+    if args.command != Command::Edit {
+        // Reports only read the .lll file, so leave the journal alone.
+        let bytes = std::fs::read(&args.db_path)
+            .map_err(|e| format!("{}: {e}", args.db_path.to_string_lossy()))?;
+        let lll = parse_lll_refs(&bytes)
+            .map_err(|e| format!("{}: {e}", args.db_path.to_string_lossy()))?;
+
+        match args.command {
+            Command::Edit => {}
+            Command::Coverage => print!("{}", coverage::report(&lll, args.canonical)),
+            Command::Templates => {
+                let templates = template::builtin()?;
+                let report = template::check(&lll, &templates);
+                print!("{report}");
+                if !report.all_usable() {
+                    std::process::exit(1);
+                }
+            }
+            Command::Generate => {
+                let templates = template::builtin()?;
+                let templates: Vec<&template::Template> = match &args.template_name {
+                    Some(name) => vec![
+                        templates.iter()
+                            .find(|template| template.name == name)
+                            .ok_or_else(|| format!("No template is called {name}"))?
+                    ],
+                    // Leave out the templates the lll cannot fill in, rather
+                    // than stopping when one of them comes up.
+                    None => templates.iter()
+                        .filter(|template| template.variants.iter()
+                            .any(|parts| template::problems(&lll, parts).is_empty()))
+                        .collect(),
+                };
+                if templates.is_empty() {
+                    return Err(
+                        "The lll does not have what any template needs. See `llledit templates`."
+                            .into()
+                    )
+                }
+
+                let mut rng = match args.seed {
+                    Some(seed) => generator::Rng::new(seed),
+                    None => generator::Rng::from_time(),
+                };
+                let mut history = generator::History::new(args.avoid_repeats);
+
+                for _ in 0..args.count {
+                    let template = templates[rng.below(templates.len())];
+                    println!(
+                        "{}",
+                        generator::generate(&lll, template, args.casing, &mut history, &mut rng)?
+                    );
+                }
+            }
         }
+
+        return Ok(())
+    }
+
+    let (mut store, lll) = Store::open(args.db_path)?;
+
+    if let Some(replay_path) = &args.replay_path {
+        return replay(&mut store, lll, replay_path, args.canonical);
+    }
+
+    let mut recording = match &args.record_path {
+        Some(path) => Some(
+            File::create(path)
+                .map_err(|e| format!("{}: {e}", path.to_string_lossy()))?
+        ),
+        None => None,
     };
 
-    let initial_len = file.metadata()?.len();
-    // Round up to nearest 256 bytes, because we expect most of the time at least
-    // one lexeme will be added.
-    let capacity = (initial_len | 0xFF) + 1;
-    let mut bytes = Vec::with_capacity(usize::try_from(capacity).unwrap_or_default());
-    file.read_to_end(&mut bytes)?;
+    let p = Printer::from_env();
 
-    let mut lll: Vec<LL> = parse_lll(&bytes)?;
+    let mut editor = Editor::new(lll, args.canonical);
 
     p.enable_alternate_screen();
     p.clear();
     p.move_home();
 
-    let mut input = String::with_capacity(usize::from(lexeme::MAX_LENGTH));
-
-    enum State {
-        Menu,
-        AddChars{ ll: LL },
-        AddFlags{ ll: LL },
-        SelectEditIndex{ index: Option<usize> },
-        EditChars{ ll: LL, index: usize },
-        EditFlags{ ll: LL, index: usize },
-    }
-
-    let mut state = State::Menu;
-    let mut err: ErrMsg = "";
+    let flag_editor_available = p.is_interactive();
 
     let stdin = std::io::stdin();
     loop {
         macro_rules! break_if_err {
             ($res: expr) => {
-                if let Err(e) = $res {
-                    eprintln!("{e}");
-                    // Do the cleanup, instead of just exiting.
-                    break
+                match $res {
+                    Ok(x) => x,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        // Do the cleanup, instead of just exiting.
+                        break
+                    }
                 }
             }
         }
 
-        p.clear();
-        p.move_home();
-
-        const FLAG_NAMES: [&str; 16] = [
-            "SINGULAR_NOUN",
-            "PLURAL_NOUN",
-            "MASS_NOUN",
-            "RESERVED",
-            "INTRANSITIVE_VERB",
-            "TRANSITIVE_VERB",
-            "RESERVED",
-            "RESERVED",
-            "THIRD_PERSON_SINGULAR_VERB",
-            "RESERVED",
-            "FIRST_PERSON_SINGULAR_VERB",
-            "RESERVED",
-            "RESERVED",
-            "RESERVED",
-            "RESERVED",
-            "RESERVED",
-        ];
+        let view = editor.view();
 
-        // Used in compile-time asserts.
-        #[allow(dead_code)]
-        const MAX_FLAG_NAME_LEN: usize = {
-            let mut max_len = 0;
-            let mut i = 0;
-            while i < FLAG_NAMES.len() {
-                let len = FLAG_NAMES[i].len();
-                if len > max_len {
-                    max_len = len;
-                }
-                i += 1;
+        let input = match view {
+            View::AddFlags{ ll, .. } if flag_editor_available => {
+                Input::FlagsCommands(
+                    break_if_err!(flag_editor::run(&p, "Add flags to", ll, None))
+                )
             }
-            max_len
-        };
-
-        const ADJECTIVE_ORDER_BLOCK_NAMES: [&str; 16] = [
-            "NONE",
-            "RESERVED",
-            "RESERVED",
-            "RESERVED",
-            "QUANTITY",
-            "OBSERVATION",
-            "SIZE",
-            "PHYSICAL",
-            "SHAPE",
-            "AGE",
-            "COLOUR",
-            "ORIGIN",
-            "MATERIAL",
-            "RESERVED",
-            "RESERVED",
-            "RESERVED",
-        ];
-
-        // Used in compile-time asserts.
-        #[allow(dead_code)]
-        const MAX_ADJECTIVE_ORDER_BLOCK_NAME_LEN: usize = {
-            let mut max_len = 0;
-            let mut i = 0;
-            while i < ADJECTIVE_ORDER_BLOCK_NAMES.len() {
-                let len = ADJECTIVE_ORDER_BLOCK_NAMES[i].len();
-                if len > max_len {
-                    max_len = len;
-                }
-                i += 1;
+            View::EditFlags{ ll, prev, .. } if flag_editor_available => {
+                let prev_flags = prev.map(|prev| prev.flags);
+                Input::FlagsCommands(
+                    break_if_err!(flag_editor::run(&p, "Edit flags for", ll, prev_flags))
+                )
             }
-            max_len
-        };
-
-        match state {
-            State::Menu => {
-                println!("a) Add a lexeme");
-                println!("e) Edit a lexeme");
-                println!("q then enter to quit");
-                println!("{err}");
-            }
-            State::AddChars{ ref mut ll } => {
-                println!("Add a lexeme");
-                println!();
-                println!("{err}");
-                print!(">{}", ll.lexeme);
-            }
-            State::AddFlags{ ref mut ll } => {
-                println!("Add flags to");
-                println!("{:#}", ll.lexeme);
-                println!(">{:#b}", ll.flags);
-                println!("To change the flags pick a operation prefix:");
-                println!("s) Set bits. t) Toggle bits. u) Un-set bits.");
-                println!("... then enter it followed by a comma-separated");
-                println!("list of bit indexes.");
-                println!("To change a block value enter a block prefix:");
-                println!("a) adjective order.");
-                println!("... then enter it followed by the desired value.");
-                println!("");
-                println!("Flags:");
-                {
-                    let half_len = (FLAG_NAMES.len() + 1) / 2;
-
-                    for i in 0..half_len {
-                        let first_name = FLAG_NAMES[i];
-                        let i2 = half_len + i;
-                        if let Some(second_name) = FLAG_NAMES.get(i2) {
-                            // assert format width is large enough
-                            compile_time_assert!(
-                                30 >= MAX_FLAG_NAME_LEN
-                            );
-                            println!("{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
-                        } else {
-                            println!("{first_name:>30}:{i:2}");
-                        }
-                    }
-                }
-                println!("Adjective Order Block:");
-                {
-                    let half_len = (ADJECTIVE_ORDER_BLOCK_NAMES.len() + 1) / 2;
-
-                    for i in 0..half_len {
-                        let first_name = ADJECTIVE_ORDER_BLOCK_NAMES[i];
-                        let i2 = half_len + i;
-                        if let Some(second_name) = ADJECTIVE_ORDER_BLOCK_NAMES.get(i2) {
-                            // assert format width is large enough
-                            compile_time_assert!(
-                                30 >= MAX_ADJECTIVE_ORDER_BLOCK_NAME_LEN
-                            );
-                            println!("{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
-                        } else {
-                            println!("{first_name:>30}:{i:2}");
-                        }
-                    }
-                }
-                println!("e) Edit lexeme. f) Finished editing flags.");
-                println!("{err}");
-            }
-            State::SelectEditIndex{ ref mut index } => {
-                println!("Select a lexeme");
-                println!("Enter an index, or");
-                println!("q) go back to the menu");
-                match index.and_then(|i| lll.get(i).map(|ll| (i, ll))) {
-                    Some((i, ll)) => {
-                        println!("e) edit this lexeme f) edit this lexeme's flags");
-                        println!();
-                        println!("{err}");
-                        println!("@{}", i);
-                        // TODO print the surrounding lexemes in the lll
-                        println!("{:#}", ll.lexeme);
-                        println!("{:#b}", ll.flags);
-                    },
-                    None => {
-                        println!();
-                        println!("{err}");
-                        print!(">");
-                    }
-                }
+            _ => {
+                render(&p, &view);
 
-            }
-            State::EditChars{ ref mut ll, index } => {
-                println!("Edit a lexeme");
-                println!();
-                println!("{err}");
-                if let Some(prev) = lll.get(index) {
-                    println!("{:#}", prev.lexeme);
-                    println!();
-                }
-                print!(">{}", ll.lexeme);
-            }
-            State::EditFlags{ ref mut ll, index } => {
-                println!("Edit flags for");
-                println!("{:#}", ll.lexeme);
-                if let Some(prev) = lll.get(index) {
-                    println!("{:#b}", prev.flags);
-                    println!();
-                }
-                println!(">{:#b}", ll.flags);
-                println!("To change the flags pick a operation prefix:");
-                println!("s) Set bits. t) Toggle bits. u) Un-set bits.");
-                println!("... then enter it followed by a comma-separated");
-                println!("list of bit indexes.");
-                println!("To change a block value enter a block prefix:");
-                println!("a) adjective order.");
-                println!("... then enter it followed by the desired value.");
-                println!("");
-                println!("Flags:");
-                {
-                    let half_len = (FLAG_NAMES.len() + 1) / 2;
-
-                    for i in 0..half_len {
-                        let first_name = FLAG_NAMES[i];
-                        let i2 = half_len + i;
-                        if let Some(second_name) = FLAG_NAMES.get(i2) {
-                            // assert format width is large enough
-                            compile_time_assert!(
-                                30 >= MAX_FLAG_NAME_LEN
-                            );
-                            println!("{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
-                        } else {
-                            println!("{first_name:>30}:{i:2}");
-                        }
-                    }
-                }
-                println!("Adjective Order Block:");
-                {
-                    let half_len = (ADJECTIVE_ORDER_BLOCK_NAMES.len() + 1) / 2;
-
-                    for i in 0..half_len {
-                        let first_name = ADJECTIVE_ORDER_BLOCK_NAMES[i];
-                        let i2 = half_len + i;
-                        if let Some(second_name) = ADJECTIVE_ORDER_BLOCK_NAMES.get(i2) {
-                            // assert format width is large enough
-                            compile_time_assert!(
-                                30 >= MAX_ADJECTIVE_ORDER_BLOCK_NAME_LEN
-                            );
-                            println!("{first_name:>30}:{i:2} {second_name:>30}:{i2:2}");
-                        } else {
-                            println!("{first_name:>30}:{i:2}");
-                        }
-                    }
+                let mut line = String::with_capacity(V0_MAX_LENGTH.into());
+                if break_if_err!(stdin.read_line(&mut line)) == 0 {
+                    // End of input, so there is nothing more we could do.
+                    break
                 }
-                println!("e) Edit lexeme. f) Finished editing flags.");
-                println!("{err}");
+                Input::Line(line)
             }
-        }
+        };
 
-        input.clear();
-        break_if_err!(stdin.read_line(&mut input));
+        if let Some(recording) = &mut recording {
+            use std::io::Write;
 
-        state = match state {
-            State::Menu => {
-                match input.chars().next() {
-                    Some('q') => break,
-                    Some('a') => {
-                        err = "";
-                        State::AddChars{ ll: <_>::default() }
-                    },
-                    Some('e') => {
-                        err = "";
-                        State::SelectEditIndex{ index: None }
-                    },
-                    None => {
-                        err = "Type a letter to select an option";
-                        state
-                    },
-                    _ => {
-                        err = "???";
-                        state
-                    }
-                }
-            }
-            State::AddChars{ mut ll } => {
-                // TODO? Check if lexeme is already in the lll?
-                match Lexeme::try_from(input.as_str()) {
-                    Ok(lexeme) => {
-                        ll.lexeme = lexeme;
-                        err = "";
-                        State::AddFlags{ ll }
-                    },
-                    Err(e) => {
-                        err = e;
-                        State::AddChars{ ll }
-                    }
-                }
-            }
-            State::AddFlags{ mut ll } => {
-                match parse_flags_commands(&input) {
-                    Ok(commands) => {
-                        let switch = handle_commands(&mut ll, &commands);
-
-                        match switch {
-                            StateSwitch::Stay => {
-                                State::AddFlags{ ll }
-                            },
-                            StateSwitch::EditLexeme => {
-                                State::AddChars{ ll }
-                            },
-                            StateSwitch::Finished => {
-                                lll.push(ll);
-
-                                break_if_err!(write_lll_to_disk(
-                                    &mut file,
-                                    &lll
-                                ));
-
-                                State::Menu
-                            },
-                        }
-                    },
-                    Err(e) => {
-                        err = e;
-                        State::AddFlags{ ll }
-                    }
-                }
-            }
-            State::SelectEditIndex{ ref mut index } => {
-                match (*index, input.chars().next()) {
-                    (_, Some('q')) => {
-                        err = "";
-                        State::Menu
-                    },
-                    (Some(i), Some('e')) => {
-                        match lll.get(i) {
-                            Some(ll) => {
-                                err = "";
-                                State::EditChars{ ll: ll.clone(), index: i }
-                            }
-                            None => {
-                                err = "No lexeme at that index";
-                                State::SelectEditIndex{ index: Some(i) }
-                            }
-                        }
-                    },
-                    (Some(i), Some('f')) => {
-                        match lll.get(i) {
-                            Some(ll) => {
-                                err = "";
-                                State::EditFlags{ ll: ll.clone(), index: i }
-                            }
-                            None => {
-                                err = "No lexeme at that index";
-                                State::SelectEditIndex{ index: Some(i) }
-                            }
-                        }
-                    },
-                    _ => {
-                        dbg!(input.as_str());
-                        // TODO? allow jumping to add a new lexeme from here?
-                        match usize::from_str_radix(input.as_str().trim(), 10) {
-                            Ok(i) => {
-                                *index = Some(i);
-                                err = "";
-                                State::SelectEditIndex{ index: *index }
-                            },
-                            Err(_) => {
-                                err = "Could not parse index";
-                                State::SelectEditIndex{ index: *index }
-                            }
-                        }
-                    }
-                }
-            }
-            State::EditChars{ mut ll, index } => {
-                // TODO? Implement actual piecewise editing,
-                // instead of just replacing?
-                match Lexeme::try_from(input.as_str()) {
-                    Ok(lexeme) => {
-                        ll.lexeme = lexeme;
-                        err = "";
-                        State::EditFlags{ ll, index }
-                    },
-                    Err(e) => {
-                        err = e;
-                        State::EditChars{ ll, index }
-                    }
-                }
-            }
-            State::EditFlags{ mut ll, index } => {
-                match parse_flags_commands(&input) {
-                    Ok(commands) => {
-                        let switch = handle_commands(&mut ll, &commands);
-
-                        match switch {
-                            StateSwitch::Stay => {
-                                State::EditFlags{ ll, index }
-                            },
-                            StateSwitch::EditLexeme => {
-                                State::EditChars{ ll, index }
-                            },
-                            StateSwitch::Finished => {
-                                if let Some(_) = lll.get(index) {
-                                    lll[index] = ll;
-                                } else {
-                                    // TODO? Break instead? check for a duplicate?
-                                    lll.push(ll);
-                                }
+            let line = recorded_line(&input);
 
-                                break_if_err!(write_lll_to_disk(
-                                    &mut file,
-                                    &lll
-                                ));
+            // Flush each line, so the recording is complete up to a crash.
+            break_if_err!(
+                recording.write_all(line.as_bytes())
+                    .and_then(|()| recording.flush())
+            );
+        }
 
-                                State::Menu
-                            },
-                        }
-                    },
-                    Err(e) => {
-                        err = e;
-                        State::EditFlags{ ll, index }
-                    }
-                }
+        match editor.handle(input) {
+            Some(Effect::Save(changes)) => {
+                break_if_err!(store.save(editor.lll(), &changes));
             }
+            Some(Effect::Quit) => break,
+            None => {}
         }
     }
 
@@ -804,27 +3062,36 @@ enum StateSwitch {
     Finished,
 }
 
+fn flag(index: FlagIndex) -> Flags {
+    // `parse_flags_commands` rejects out of range indexes, so this should
+    // only be hit if a `FlagsCommand` was constructed some other way.
+    debug_assert!(index < FLAG_COUNT);
+    if index < FLAG_COUNT {
+        1 << (index as Flags)
+    } else {
+        0
+    }
+}
+
 fn handle_commands(ll: &mut LL, commands: &[FlagsCommand]) -> StateSwitch {
     let mut switch = StateSwitch::Stay;
     for command in commands.iter() {
         use FlagsCommand::*;
         match *command {
             Set(index) => {
-                let flag: Flags = 1 << (index as Flags);
-                ll.flags |= flag;
+                ll.flags |= flag(index);
             }
             Toggle(index) => {
-                let flag: Flags = 1 << (index as Flags);
-                ll.flags ^= flag;
+                ll.flags ^= flag(index);
             }
             Unset(index) => {
-                let flag: Flags = 1 << (index as Flags);
-                ll.flags &= !flag;
+                ll.flags &= !flag(index);
             }
             SetAdjectiveOrder(category) => {
-                const ADJECTIVE_ORDER_SHIFT: Flags = 16;
-                ll.flags &= !(0b1111 << ADJECTIVE_ORDER_SHIFT);
-                ll.flags |= (category as Flags) << ADJECTIVE_ORDER_SHIFT;
+                debug_assert!(category < ADJECTIVE_ORDER_CATEGORY_COUNT);
+                ll.flags &= !ADJECTIVE_ORDER_MASK;
+                ll.flags |= ((category as Flags) << ADJECTIVE_ORDER_SHIFT)
+                    & ADJECTIVE_ORDER_MASK;
             }
             EditLexeme => {
                 switch = StateSwitch::EditLexeme;
@@ -839,24 +3106,358 @@ fn handle_commands(ll: &mut LL, commands: &[FlagsCommand]) -> StateSwitch {
     switch
 }
 
-fn write_lll_to_disk(file: &mut File, lll: &[LL]) -> io::Result<()> {
-    use std::io::{Seek, SeekFrom, Write};
+fn encode_block_header(
+    output: &mut Vec<u8>,
+    version: Version,
+    fef: bool,
+    body_len: usize,
+    header: [u8; 3],
+) {
+    let len = version.block_header_length() + body_len;
+    match version {
+        Version::V0 => {
+            debug_assert!(len <= usize::from(V0_MAX_LENGTH));
+            output.push(len as u8 | if fef { 0x80 } else { 0 });
+        }
+        Version::V1 => {
+            debug_assert!(len <= usize::from(V1_MAX_LENGTH));
+            let len = len as u16 | if fef { 0x8000 } else { 0 };
+            output.extend_from_slice(&len.to_le_bytes());
+        }
+    }
+    output.extend_from_slice(&header);
+}
+
+/// Encodes the lexeme's block, followed by a block for each of its extensions.
+fn encode_block(output: &mut Vec<u8>, ll: &LL, version: Version) {
+    let [flags0, flags1, flags2, _] = ll.flags.to_le_bytes();
+    encode_block_header(
+        output,
+        version,
+        false,
+        usize::from(ll.lexeme.len()),
+        [flags0, flags1, flags2],
+    );
+    output.extend_from_slice(ll.lexeme.bytes());
+
+    for extension in &ll.extensions {
+        let value = extension.value();
+        encode_block_header(
+            output,
+            version,
+            true,
+            value.len(),
+            [extension.kind(), extension.key(), 0],
+        );
+        output.extend_from_slice(&value);
+    }
+}
+
+/// The lowest version that can hold `ll` and its extensions.
+fn lowest_version_for(ll: &LL) -> Version {
+    ll.extensions.iter()
+        .map(|extension| Version::lowest_for(extension.value().len()))
+        .fold(
+            Version::lowest_for(usize::from(ll.lexeme.len())),
+            Version::max,
+        )
+}
+
+/// The lowest version that can hold all of `lll`.
+fn lowest_version(lll: &[LL]) -> Version {
+    lll.iter()
+        .map(lowest_version_for)
+        .max()
+        .unwrap_or(Version::V0)
+}
+
+fn encode_lll(lll: &[LL]) -> Vec<u8> {
+    let version = lowest_version(lll);
 
-    file.seek(SeekFrom::Start(0))?;
-    file.set_len(0)?;
+    let mut output = Vec::with_capacity(V0_HEADER.len() + lll.len() * 16);
 
-    file.write(&V0_HEADER)?;
+    output.extend_from_slice(&version.header());
     for ll in lll {
-        file.write(&[
-            V0_MIN_LENGTH + ll.lexeme.len() as u8
-        ])?;
+        encode_block(&mut output, ll, version);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printer::Recording;
 
-        file.write(&(ll.flags.to_le_bytes())[0..3])?;
+    fn line(s: &str) -> Input {
+        Input::Line(format!("{s}\n"))
+    }
+
+    #[test]
+    fn parse_flags_commands_checks_the_ranges() {
+        use FlagsCommand::*;
+
+        let cases: &[(&str, Result<&[FlagsCommand], ErrMsg>)] = &[
+            ("s23\n", Ok(&[Set(23)])),
+            ("s24\n", Err(FLAG_INDEX_RANGE_ERROR)),
+            ("t255\n", Err(FLAG_INDEX_RANGE_ERROR)),
+            ("u1000\n", Err(FLAG_INDEX_RANGE_ERROR)),
+            ("a15\n", Ok(&[SetAdjectiveOrder(15)])),
+            ("a16\n", Err(ADJECTIVE_ORDER_CATEGORY_RANGE_ERROR)),
+            ("s0,23 u1\n", Ok(&[Set(0), Set(23), Unset(1)])),
+        ];
 
-        file.write(ll.lexeme.bytes())?;
+        for &(input, expected) in cases {
+            assert_eq!(
+                parse_flags_commands(input).map(Vec::from),
+                expected.map(<[_]>::to_vec),
+                "{input:?}"
+            );
+        }
     }
 
-    file.flush()?;
+    #[test]
+    fn parse_flags_commands_does_not_need_a_trailing_newline() {
+        use FlagsCommand::*;
 
-    Ok(())
-}
\ No newline at end of file
+        assert_eq!(parse_flags_commands("s1,2").map(Vec::from), Ok(vec![Set(1), Set(2)]));
+        assert_eq!(parse_flags_commands("a9").map(Vec::from), Ok(vec![SetAdjectiveOrder(9)]));
+        assert_eq!(parse_flags_commands("s24").map(Vec::from), Err(FLAG_INDEX_RANGE_ERROR));
+    }
+
+    #[test]
+    fn flags_commands_round_trip_through_a_line() {
+        use FlagsCommand::*;
+
+        let commands = [
+            Set(0),
+            Toggle(23),
+            Unset(10),
+            SetAdjectiveOrder(15),
+            SetAdjectiveOrder(0),
+            EditLexeme,
+        ];
+        for end in 0..=commands.len() {
+            let line = flags_commands_to_line(&commands[..end]);
+            assert_eq!(parse_flags_commands(&line).map(Vec::from), Ok(commands[..end].to_vec()));
+        }
+        assert_eq!(
+            parse_flags_commands(&flags_commands_to_line(&[Set(1), FinishedFlags])).map(Vec::from),
+            Ok(vec![Set(1), FinishedFlags]),
+        );
+    }
+
+    #[test]
+    fn replaying_a_recorded_session_gives_the_same_lll() {
+        let dir = TempDir::new("replay");
+        let start = [ll("cows", PLURAL_NOUN)];
+        let recorded_path = dir.lll(&start);
+
+        // Like the flag editor, some input comes as commands, rather
+        // than lines.
+        let inputs = [
+            Input::Line("a\n".into()),
+            Input::Line("cow\n".into()),
+            Input::FlagsCommands(Box::new([FlagsCommand::Set(0), FlagsCommand::FinishedFlags])),
+            Input::Line("y\n".into()),
+            Input::Line("a\n".into()),
+            Input::Line("moo".into()),
+            Input::FlagsCommands(Box::new([
+                FlagsCommand::Set(4),
+                FlagsCommand::Set(10),
+                FlagsCommand::FinishedFlags,
+            ])),
+            Input::Line("n\n".into()),
+            Input::Line("e\n".into()),
+            Input::Line("cow\n".into()),
+            Input::Line("m\n".into()),
+            Input::Line("note: moos\n".into()),
+            Input::Line("f\n".into()),
+            Input::Line("q\n".into()),
+        ];
+
+        let (mut store, lll) = Store::open(recorded_path.clone()).unwrap();
+        let mut editor = Editor::new(lll, canonical::Options::default());
+        let mut recording = String::new();
+        for input in inputs {
+            recording.push_str(&recorded_line(&input));
+            match editor.handle(input) {
+                Some(Effect::Save(changes)) => store.save(editor.lll(), &changes).unwrap(),
+                Some(Effect::Quit) => break,
+                None => {}
+            }
+        }
+        drop(store);
+        let recorded = std::fs::read(&recorded_path).unwrap();
+        let recorded_lll = parse_lll(&recorded).unwrap();
+        assert_eq!(recorded_lll.len(), 3);
+        assert!(recorded_lll[1].extensions.contains(&Extension::Note("moos".into())));
+
+        let replayed_dir = TempDir::new("replayed");
+        let replayed_path = replayed_dir.lll(&start);
+        let recording_path = replayed_dir.0.join("session.txt");
+        std::fs::write(&recording_path, recording).unwrap();
+
+        let (mut store, lll) = Store::open(replayed_path.clone()).unwrap();
+        replay(&mut store, lll, &recording_path, canonical::Options::default()).unwrap();
+        drop(store);
+
+        assert_eq!(std::fs::read(&replayed_path).unwrap(), recorded);
+    }
+
+    #[test]
+    fn render_starts_a_new_screen_each_time() {
+        let recording = Recording::default();
+        let p = Printer::new(Box::new(recording.clone()));
+        let mut editor = Editor::new(Vec::new(), canonical::Options::default());
+
+        render(&p, &editor.view());
+        editor.handle(line("x"));
+        render(&p, &editor.view());
+
+        let screens = recording.screens();
+        assert_eq!(screens.len(), 2);
+        assert!(screens[0].starts_with("a) Add a lexeme\ne) Edit a lexeme\n"));
+        assert!(!screens[0].contains("???"));
+        assert!(screens[1].ends_with("???\n"));
+    }
+
+    #[test]
+    fn render_shows_a_selected_lexeme_with_its_metadata_and_relations() {
+        let mut cow = ll("cow", SINGULAR_NOUN);
+        cow.extensions.push(Extension::Note("moos".into()));
+        cow.extensions.push(Extension::Related{
+            relation: extension::Relation::Plural,
+            index: 1,
+        });
+        let lll = vec![cow, ll("cows", PLURAL_NOUN)];
+
+        let recording = Recording::default();
+        let p = Printer::new(Box::new(recording.clone()));
+        let mut editor = Editor::new(lll, canonical::Options::default());
+
+        editor.handle(line("e"));
+        editor.handle(line("cow"));
+        render(&p, &editor.view());
+        let screen = recording.last_screen().unwrap();
+        assert!(screen.contains("@0\n\"cow\"\n"));
+        assert!(screen.contains("note: moos\n"));
+        assert!(screen.contains("plural: @1 \"cows\"\n"));
+
+        editor.handle(line("1"));
+        render(&p, &editor.view());
+        let screen = recording.last_screen().unwrap();
+        assert!(screen.contains("@1\n\"cows\"\n"));
+        assert!(screen.contains("singular: @0 \"cow\"\n"));
+    }
+
+    /// Checks that `lll` is written as `version`, and reads back, and writes
+    /// again, exactly as it was.
+    fn assert_round_trips(lll: &[LL], version: Version) {
+        let bytes = encode_lll(lll);
+        assert_eq!(bytes[..4], version.header());
+
+        let (parsed, parsed_version, complete_end) = parse_lll_with_version(&bytes).unwrap();
+        assert_eq!(parsed_version, version);
+        assert_eq!(complete_end, bytes.len());
+        assert_eq!(parsed.len(), lll.len());
+        for (parsed, ll) in parsed.iter().zip(lll) {
+            assert_eq!(parsed.lexeme.as_str(), ll.lexeme.as_str());
+            assert_eq!(parsed.flags, ll.flags);
+            assert_eq!(parsed.extensions, ll.extensions);
+        }
+
+        assert_eq!(encode_lll(&parsed), bytes);
+    }
+
+    fn with_every_extension(mut ll: LL) -> LL {
+        ll.extensions.extend([
+            Extension::Frequency(7),
+            Extension::Note("moos".into()),
+            Extension::Source("a field".into()),
+            Extension::Tag("farm".into()),
+            Extension::Tag("animal".into()),
+            Extension::Article(inflection::Article::A),
+            Extension::KeepCase,
+            Extension::Related{ relation: extension::Relation::Plural, index: 1 },
+            Extension::Unknown{ kind: 9, key: 3, value: Box::new([0, 0xFF, 0x80]) },
+            Extension::Unknown{
+                kind: extension::METADATA_KIND,
+                key: 42,
+                value: Box::new([]),
+            },
+        ]);
+        ll
+    }
+
+    #[test]
+    fn version_0_blocks_round_trip_with_their_extensions() {
+        let lll = [
+            with_every_extension(ll("cow", SINGULAR_NOUN)),
+            ll("cows", PLURAL_NOUN),
+            ll("moo", INTRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB),
+        ];
+
+        assert_round_trips(&lll, Version::V0);
+    }
+
+    #[test]
+    fn version_1_blocks_round_trip_with_their_extensions() {
+        let long = "m".repeat(Version::V0.max_lexeme_length() + 1);
+        let lll = [
+            with_every_extension(ll("cow", SINGULAR_NOUN)),
+            ll("cows", PLURAL_NOUN),
+            ll(&long, INTRANSITIVE_VERB),
+        ];
+
+        assert_round_trips(&lll, Version::V1);
+    }
+
+    #[test]
+    fn long_unknown_extensions_are_kept_in_version_1() {
+        let mut cow = ll("cow", SINGULAR_NOUN);
+        cow.extensions.push(Extension::Unknown{
+            kind: 9,
+            key: 3,
+            value: vec![b'x'; Version::V0.max_lexeme_length() + 1].into(),
+        });
+
+        assert_round_trips(&[cow], Version::V1);
+    }
+
+    #[test]
+    fn blocks_borrow_the_lexemes_from_the_input() {
+        let mut cow = ll("cow", SINGULAR_NOUN);
+        cow.extensions.push(Extension::Frequency(3));
+        let bytes = encode_lll(&[cow, ll("moo", INTRANSITIVE_VERB)]);
+        let input = bytes.as_ptr_range();
+
+        let mut blocks = Blocks::new(&bytes).unwrap();
+        let lll: Vec<LLRef<'_>> = blocks.by_ref().map(Result::unwrap).collect();
+
+        assert_eq!(lll.len(), 2);
+        for ll in &lll {
+            assert!(input.contains(&ll.lexeme.as_ptr()));
+        }
+        assert_eq!(lll[0].lexeme, "cow");
+        assert_eq!(lll[0].parsed_extensions().count(), 1);
+        assert_eq!(lll[1].lexeme, "moo");
+        assert_eq!(lll[1].flags, INTRANSITIVE_VERB);
+        assert_eq!(blocks.complete_end(), bytes.len());
+    }
+
+    #[test]
+    fn blocks_stop_at_a_truncated_trailing_block() {
+        let complete = encode_lll(&[ll("cow", SINGULAR_NOUN)]);
+        let full = encode_lll(&[ll("cow", SINGULAR_NOUN), ll("moo", INTRANSITIVE_VERB)]);
+
+        for end in complete.len() + 1..full.len() {
+            let mut blocks = Blocks::new(&full[..end]).unwrap();
+            let lll: Vec<LLRef<'_>> = blocks.by_ref().map(Result::unwrap).collect();
+
+            assert_eq!(lll.len(), 1);
+            assert_eq!(lll[0].lexeme, "cow");
+            assert_eq!(blocks.complete_end(), complete.len());
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! Keeps the .lll file on disk in sync with the edits made to it, such that
+//! completed edits survive a crash.
+//!
+//! Before the .lll file is written, each change is appended to a journal file
+//! next to it. Once the .lll file has been written, the journal is cleared.
+//! So if there is anything in the journal when we start, then the last
+//! session ended before some changes made it into the .lll file, and we
+//! replay them.
+//!
+//! The journal format is a magic number, followed by a base record, then any
+//! number of save records, each holding the changes saved together. All
+//! integers are little endian.
+//!
+//! | Magic   | Base                                  |
+//! | "llj\3" | .lll length: 8 bytes, .lll hash: 8 bytes |
+//!
+//! | Change count | Changes                        | Resulting .lll length | Resulting .lll hash |
+//! | 8 bytes      | As many change records as that | 8 bytes               | 8 bytes             |
+//!
+//! | Tag    | Index (Replaced only) | Blocks length | Blocks                                          |
+//! | 1 byte | 8 bytes               | 8 bytes       | A version 1 .lll block and its extension blocks |
+//!
+//! The base record describes the .lll file the changes should be applied to,
+//! and the length and hash after each save tell us which saves, if any, have
+//! already made it into the .lll file. A save record is only replayed if it
+//! was completely written, so the changes in it, which may refer to each
+//! other, are replayed all together or not at all.
+
+use super::*;
+use std::{
+    fs::OpenOptions,
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const MAGIC: [u8; 4] = [b'l', b'l', b'j', 3];
+const ADDED_TAG: u8 = 1;
+const REPLACED_TAG: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added{ index: usize },
+    Replaced{ index: usize },
+}
+
+/// The length and hash of an entire .lll file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Summary {
+    len: u64,
+    hash: u64,
+}
+
+impl Summary {
+    fn of(bytes: &[u8]) -> Self {
+        Self {
+            len: 0,
+            // FNV-1a offset basis.
+            hash: 0xcbf2_9ce4_8422_2325,
+        }.extended(bytes)
+    }
+
+    /// The summary of the file this summary describes, with `bytes`
+    /// appended to it.
+    fn extended(self, bytes: &[u8]) -> Self {
+        // FNV-1a. We only need to notice accidental differences, so
+        // anything fancier would be overkill. It also lets us extend the
+        // hash without looking at the previous bytes again.
+        let mut hash = self.hash;
+        for &b in bytes {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+
+        Self {
+            len: self.len + bytes.len() as u64,
+            hash,
+        }
+    }
+
+    fn encode(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&self.len.to_le_bytes());
+        output.extend_from_slice(&self.hash.to_le_bytes());
+    }
+}
+
+enum Record {
+    Added(LL),
+    Replaced{ index: usize, ll: LL },
+}
+
+struct Journal {
+    base: Summary,
+    /// The changes from each save, along with the summary of the .lll
+    /// after them.
+    saves: Vec<(Vec<Record>, Summary)>,
+}
+
+/// Returns `None` if there is no usable journal. Since the journal is
+/// only cleared after the .lll file is written, a journal with a
+/// partially written magic number or base record has nothing in it that
+/// still needs replaying. Similarly, a partially written save record was
+/// never written to the .lll file, so none of its changes were completed.
+fn parse_journal(bytes: &[u8]) -> Option<Journal> {
+    fn take<'bytes>(bytes: &mut &'bytes [u8], n: usize) -> Option<&'bytes [u8]> {
+        if bytes.len() < n {
+            return None
+        }
+        let (taken, rest) = bytes.split_at(n);
+        *bytes = rest;
+        Some(taken)
+    }
+
+    fn take_u64(bytes: &mut &[u8]) -> Option<u64> {
+        take(bytes, 8)?.try_into().ok().map(u64::from_le_bytes)
+    }
+
+    fn take_summary(bytes: &mut &[u8]) -> Option<Summary> {
+        Some(Summary {
+            len: take_u64(bytes)?,
+            hash: take_u64(bytes)?,
+        })
+    }
+
+    fn take_ll(bytes: &mut &[u8]) -> Option<LL> {
+        let len = usize::try_from(take_u64(bytes)?).ok()?;
+        let mut blocks = Blocks::headerless(take(bytes, len)?, Version::V1);
+        let ll = blocks.next()?.ok()?;
+        if blocks.next().is_some() {
+            return None
+        }
+        ll.try_into().ok()
+    }
+
+    fn take_record(bytes: &mut &[u8]) -> Option<Record> {
+        match take(bytes, 1)?[0] {
+            ADDED_TAG => Some(Record::Added(take_ll(bytes)?)),
+            REPLACED_TAG => {
+                let index = take_u64(bytes)? as usize;
+                Some(Record::Replaced{ index, ll: take_ll(bytes)? })
+            }
+            _ => None,
+        }
+    }
+
+    fn take_save(bytes: &mut &[u8]) -> Option<(Vec<Record>, Summary)> {
+        let count = take_u64(bytes)?;
+        let mut records = Vec::new();
+        for _ in 0..count {
+            records.push(take_record(bytes)?);
+        }
+        Some((records, take_summary(bytes)?))
+    }
+
+    let mut bytes = bytes;
+    if take(&mut bytes, MAGIC.len())? != MAGIC {
+        return None
+    }
+
+    let base = take_summary(&mut bytes)?;
+
+    let mut saves = Vec::new();
+    while let Some(save) = take_save(&mut bytes) {
+        saves.push(save);
+    }
+
+    Some(Journal { base, saves })
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    let mut journal_path = path.to_path_buf().into_os_string();
+    journal_path.push(".journal");
+    PathBuf::from(journal_path)
+}
+
+/// Replaces the file at `path` such that it either has its old contents
+/// or all of `bytes`, even if we crash partway through.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temp_path = path.to_path_buf().into_os_string();
+    temp_path.push(".tmp");
+
+    let mut temp = File::create(&temp_path)?;
+    temp.write_all(bytes)?;
+    temp.sync_all()?;
+    drop(temp);
+
+    std::fs::rename(&temp_path, path)
+}
+
+/// What `Store::save` is about to write to the .lll file, once the
+/// changes are safely in the journal.
+struct PendingWrite {
+    /// Either blocks to append, or a whole .lll file.
+    bytes: Vec<u8>,
+    is_append: bool,
+    /// Describes the .lll file after the write.
+    summary: Summary,
+    count: usize,
+    version: Version,
+}
+
+pub struct Store {
+    path: PathBuf,
+    journal: File,
+    /// Describes what we last wrote to the .lll file.
+    on_disk: Summary,
+    /// How many lexemes we last wrote to the .lll file.
+    on_disk_count: usize,
+    /// The version we last wrote the .lll file as.
+    on_disk_version: Version,
+    /// Whether the .lll file on disk ends with a complete block. If it
+    /// does not, say because something else crashed while writing it,
+    /// then anything appended would be swallowed by the partial block.
+    appendable: bool,
+}
+
+impl Store {
+    /// Reads the .lll file at `path`, replaying any changes from the
+    /// journal that had not made it into the .lll file yet.
+    pub fn open(path: PathBuf) -> Result<(Self, Vec<LL>), Box<dyn std::error::Error>> {
+        let display = |path: &Path| path.to_string_lossy().into_owned();
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {e}", display(&path)))?;
+
+        let initial_len = file.metadata()?.len();
+        // Round up to nearest 256 bytes, because we expect most of the
+        // time at least one lexeme will be added.
+        let capacity = (initial_len | 0xFF) + 1;
+        let mut bytes = Vec::with_capacity(usize::try_from(capacity).unwrap_or_default());
+        file.read_to_end(&mut bytes)?;
+        drop(file);
+
+        let journal_path = journal_path(&path);
+
+        let mut journal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&journal_path)
+            .map_err(|e| format!("{}: {e}", display(&journal_path)))?;
+
+        let mut journal_bytes = Vec::new();
+        journal.read_to_end(&mut journal_bytes)?;
+
+        let mut lll = None;
+
+        // With no save records there is nothing to replay, so it
+        // doesn't matter if the .lll file was changed by something else,
+        // say a `git pull`, since we last saw it.
+        if let Some(parsed) = parse_journal(&journal_bytes)
+            .filter(|parsed| !parsed.saves.is_empty()) {
+            let current = Summary::of(&bytes);
+
+            let summaries: Vec<Summary> = std::iter::once(parsed.base)
+                .chain(parsed.saves.iter().map(|(_, summary)| *summary))
+                .collect();
+
+            let applied_count = match summaries.iter().rposition(|&summary| summary == current) {
+                Some(count) => count,
+                None => {
+                    // If we crashed while appending, there will be part of
+                    // a block on the end, after a version we know about.
+                    let count = summaries.iter().rposition(|&summary| {
+                        usize::try_from(summary.len).ok()
+                            .and_then(|len| bytes.get(..len))
+                            .is_some_and(|prefix| Summary::of(prefix) == summary)
+                    }).ok_or_else(|| format!(
+                        "{} does not match its journal, {}. It may be damaged, or \
+                        have been changed by another program. Move one of them \
+                        aside to continue.",
+                        display(&path),
+                        display(&journal_path),
+                    ))?;
+
+                    bytes.truncate(summaries[count].len as usize);
+
+                    count
+                }
+            };
+
+            let to_replay: Vec<&Record> = parsed.saves[applied_count..].iter()
+                .flat_map(|(records, _)| records)
+                .collect();
+            if to_replay.is_empty() {
+                if bytes.len() as u64 != initial_len {
+                    // We truncated a partial append.
+                    write_atomically(&path, &bytes)?;
+                }
+            } else {
+                let mut replayed = parse_lll(&bytes)
+                    .map_err(|e| format!("{}: {e}", display(&path)))?;
+                for record in &to_replay {
+                    match record {
+                        Record::Added(ll) => replayed.push(ll.clone()),
+                        Record::Replaced{ index, ll } => {
+                            match replayed.get_mut(*index) {
+                                Some(old) => *old = ll.clone(),
+                                None => return Err(format!(
+                                    "{} refers to a lexeme that is not in {}",
+                                    display(&journal_path),
+                                    display(&path),
+                                ).into()),
+                            }
+                        }
+                    }
+                }
+
+                bytes = encode_lll(&replayed);
+                write_atomically(&path, &bytes)?;
+
+                eprintln!(
+                    "Recovered {} unsaved change(s) from {}",
+                    to_replay.len(),
+                    display(&journal_path),
+                );
+
+                lll = Some(replayed);
+            }
+        }
+
+        let (lll, on_disk_version, complete_end) = match lll {
+            Some(lll) => {
+                let version = lowest_version(&lll);
+                (lll, version, bytes.len())
+            }
+            None => parse_lll_with_version(&bytes)
+                .map_err(|e| format!("{}: {e}", display(&path)))?,
+        };
+
+        let mut store = Self {
+            path,
+            journal,
+            on_disk: Summary::of(&bytes),
+            on_disk_count: lll.len(),
+            on_disk_version,
+            appendable: complete_end == bytes.len(),
+        };
+
+        store.checkpoint()?;
+
+        Ok((store, lll))
+    }
+
+    /// Durably records `changes`, which have already been made to `lll`,
+    /// then writes out `lll`. The changes are saved together, so after a
+    /// crash either all of them or none of them will be in the .lll file,
+    /// before the journal is replayed.
+    ///
+    /// If `changes` only added lexemes to the end, and those lexemes fit
+    /// in the version already on disk, then only those lexemes are
+    /// written, so adding stays cheap, no matter how large the .lll file
+    /// gets. Otherwise the whole .lll file is rewritten, as the lowest
+    /// version that can hold it.
+    pub fn save(&mut self, lll: &[LL], changes: &[Change]) -> io::Result<()> {
+        let pending = self.journal_changes(lll, changes)?;
+        self.write(pending)?;
+        self.checkpoint()
+    }
+
+    /// The first half of `save`: works out what to write to the .lll
+    /// file, and durably records `changes` in the journal.
+    fn journal_changes(&mut self, lll: &[LL], changes: &[Change]) -> io::Result<PendingWrite> {
+        let is_append = self.appendable
+            && !changes.is_empty()
+            && lll.len() == self.on_disk_count + changes.len()
+            && changes.iter().enumerate().all(|(i, change)| matches!(
+                *change,
+                Change::Added{ index } if index == self.on_disk_count + i
+                    && lowest_version_for(&lll[index]) <= self.on_disk_version
+            ));
+
+        let (bytes, summary, version) = if is_append {
+            let mut blocks = Vec::with_capacity(64 * changes.len());
+            for ll in &lll[self.on_disk_count..] {
+                encode_block(&mut blocks, ll, self.on_disk_version);
+            }
+            let summary = self.on_disk.extended(&blocks);
+            (blocks, summary, self.on_disk_version)
+        } else {
+            let bytes = encode_lll(lll);
+            let summary = Summary::of(&bytes);
+            (bytes, summary, lowest_version(lll))
+        };
+
+        let mut records = Vec::with_capacity(32 + 64 * changes.len());
+        records.extend_from_slice(&(changes.len() as u64).to_le_bytes());
+        for &change in changes {
+            let index = match change {
+                Change::Added{ index } => {
+                    records.push(ADDED_TAG);
+                    index
+                }
+                Change::Replaced{ index } => {
+                    records.push(REPLACED_TAG);
+                    records.extend_from_slice(&(index as u64).to_le_bytes());
+                    index
+                }
+            };
+            let ll = lll.get(index).ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Change index was out of bounds",
+            ))?;
+            let mut blocks = Vec::with_capacity(64);
+            encode_block(&mut blocks, ll, Version::V1);
+            records.extend_from_slice(&(blocks.len() as u64).to_le_bytes());
+            records.extend_from_slice(&blocks);
+        }
+        summary.encode(&mut records);
+
+        self.journal.seek(SeekFrom::End(0))?;
+        self.journal.write_all(&records)?;
+        self.journal.sync_data()?;
+
+        Ok(PendingWrite {
+            bytes,
+            is_append,
+            summary,
+            count: lll.len(),
+            version,
+        })
+    }
+
+    /// The second half of `save`: writes out what `journal_changes`
+    /// worked out.
+    fn write(&mut self, pending: PendingWrite) -> io::Result<()> {
+        if pending.is_append {
+            let mut file = OpenOptions::new().append(true).open(&self.path)?;
+            file.write_all(&pending.bytes)?;
+            file.sync_data()?;
+        } else {
+            write_atomically(&self.path, &pending.bytes)?;
+        }
+
+        self.on_disk = pending.summary;
+        self.on_disk_count = pending.count;
+        self.on_disk_version = pending.version;
+        // Either way, the file now ends with one of our blocks.
+        self.appendable = true;
+
+        Ok(())
+    }
+
+    /// Clears the journal, given that the .lll file on disk is described
+    /// by `self.on_disk`.
+    fn checkpoint(&mut self) -> io::Result<()> {
+        let mut journal = Vec::with_capacity(MAGIC.len() + 16);
+        journal.extend_from_slice(&MAGIC);
+        self.on_disk.encode(&mut journal);
+
+        self.journal.set_len(0)?;
+        self.journal.seek(SeekFrom::Start(0))?;
+        self.journal.write_all(&journal)?;
+        self.journal.sync_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexemes(lll: &[LL]) -> Vec<&str> {
+        lll.iter().map(|ll| ll.lexeme.as_str()).collect()
+    }
+
+    /// Checks that there is nothing left in the journal to replay.
+    fn assert_checkpointed(path: &Path) {
+        let journal = std::fs::read(journal_path(path)).unwrap();
+        let parsed = parse_journal(&journal).unwrap();
+        assert!(parsed.saves.is_empty());
+        assert!(parsed.base == Summary::of(&std::fs::read(path).unwrap()));
+    }
+
+    #[test]
+    fn appends_added_lexemes() {
+        let dir = TempDir::new("append");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN)]);
+        let before = std::fs::read(&path).unwrap();
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        lll.push(ll("hat", SINGULAR_NOUN));
+        lll.push(ll("hats", PLURAL_NOUN));
+        lll[1].extensions.push(Extension::Related{
+            relation: extension::Relation::Plural,
+            index: 2,
+        });
+        let changes = [Change::Added{ index: 1 }, Change::Added{ index: 2 }];
+        let pending = store.journal_changes(&lll, &changes).unwrap();
+        assert!(pending.is_append);
+        store.write(pending).unwrap();
+        store.checkpoint().unwrap();
+        drop(store);
+
+        let after = std::fs::read(&path).unwrap();
+        assert_eq!(after[..before.len()], before);
+        assert_eq!(after, encode_lll(&lll));
+
+        let (_, reopened) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&reopened), ["cow", "hat", "hats"]);
+        assert_eq!(reopened[1].extensions, lll[1].extensions);
+        assert_checkpointed(&path);
+    }
+
+    #[test]
+    fn rewrites_instead_of_appending_after_a_partial_block() {
+        let dir = TempDir::new("partial-block");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN)]);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0x10, 0x01, 0x00]).unwrap();
+        drop(file);
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&lll), ["cow"]);
+        lll.push(ll("zebra", SINGULAR_NOUN));
+        store.save(&lll, &[Change::Added{ index: 1 }]).unwrap();
+        drop(store);
+
+        assert_eq!(std::fs::read(&path).unwrap(), encode_lll(&lll));
+        let (_, reopened) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&reopened), ["cow", "zebra"]);
+    }
+
+    #[test]
+    fn replays_after_a_partial_append() {
+        let dir = TempDir::new("partial-append");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN)]);
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        lll.push(ll("hat", SINGULAR_NOUN));
+        let pending = store.journal_changes(&lll, &[Change::Added{ index: 1 }]).unwrap();
+        assert!(pending.is_append);
+
+        // Crash partway through appending the block.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&pending.bytes[..3]).unwrap();
+        drop(file);
+        drop(store);
+
+        let (_, recovered) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&recovered), ["cow", "hat"]);
+        assert_eq!(std::fs::read(&path).unwrap(), encode_lll(&lll));
+        assert_checkpointed(&path);
+    }
+
+    #[test]
+    fn replays_after_an_unfinished_rewrite() {
+        let dir = TempDir::new("unfinished-rewrite");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN)]);
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        lll[0].flags |= MASS_NOUN;
+        lll[1].extensions.push(Extension::Note("on a cow".into()));
+        let changes = [Change::Replaced{ index: 0 }, Change::Replaced{ index: 1 }];
+        let pending = store.journal_changes(&lll, &changes).unwrap();
+        assert!(!pending.is_append);
+
+        // Crash partway through writing the temporary file, so the .lll
+        // file was never replaced.
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, &pending.bytes[..pending.bytes.len() / 2]).unwrap();
+        drop(store);
+
+        let (_, recovered) = Store::open(path.clone()).unwrap();
+        assert_eq!(recovered[0].flags, SINGULAR_NOUN | MASS_NOUN);
+        assert_eq!(recovered[1].extensions, [Extension::Note("on a cow".into())]);
+        assert_eq!(std::fs::read(&path).unwrap(), encode_lll(&lll));
+        assert_checkpointed(&path);
+    }
+
+    #[test]
+    fn does_not_replay_part_of_a_save() {
+        let dir = TempDir::new("torn-save");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN)]);
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        lll.push(ll("hat", SINGULAR_NOUN));
+        lll.push(ll("hats", PLURAL_NOUN));
+        lll[1].extensions.push(Extension::Related{
+            relation: extension::Relation::Plural,
+            index: 2,
+        });
+        let changes = [Change::Added{ index: 1 }, Change::Added{ index: 2 }];
+        store.journal_changes(&lll, &changes).unwrap();
+        drop(store);
+
+        // Crash partway through writing the journal, after "hat" but
+        // before all of "hats".
+        let journal_path = journal_path(&path);
+        let journal = std::fs::read(&journal_path).unwrap();
+        std::fs::write(&journal_path, &journal[..journal.len() - 5]).unwrap();
+
+        let (_, recovered) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&recovered), ["cow"]);
+        assert_checkpointed(&path);
+    }
+
+    #[test]
+    fn does_not_replay_changes_that_were_written() {
+        let dir = TempDir::new("already-written");
+        let path = dir.lll(&[ll("cow", SINGULAR_NOUN)]);
+
+        let (mut store, mut lll) = Store::open(path.clone()).unwrap();
+        lll.push(ll("hat", SINGULAR_NOUN));
+        let pending = store.journal_changes(&lll, &[Change::Added{ index: 1 }]).unwrap();
+        // Crash after writing the .lll file, but before the checkpoint.
+        store.write(pending).unwrap();
+        drop(store);
+
+        let (_, reopened) = Store::open(path.clone()).unwrap();
+        assert_eq!(lexemes(&reopened), ["cow", "hat"]);
+        assert_checkpointed(&path);
+    }
+}
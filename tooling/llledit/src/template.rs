@@ -0,0 +1,833 @@
+//! Snowclone templates, in the same style as the ones in index.html, and
+//! checks on whether an lll has what they need.
+//!
+//! A template is text, with slots in braces, like "Many {plural-noun} make
+//! light work." Each slot is filled with a lexeme from the slot's category.
+//! A slot can also have a name, like "{noun=hero}", and slots with the same
+//! name get the same lexeme. Or a distinct group, like "{noun!n}", and slots
+//! in the same distinct group get different lexemes. Adjective slots can
+//! take more than one adjective, like "{adjective*3}", for a phrase like
+//! "big old red", and "{adjective*3,}" also allows coordinate adjectives,
+//! like "clever, kind old". Single adjective slots can be in a kin group,
+//! like "{adjective~k}", and slots in the same kin group get adjectives from
+//! the same adjective order category, so "red is the new blue" can happen,
+//! but not "red is the new old".
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Noun,
+    SingularNoun,
+    PluralNoun,
+    MassNoun,
+    SingularOrMassNoun,
+    PluralOrMassNoun,
+    FirstPersonVerb,
+    ThirdPersonVerb,
+    IntransitiveFirstPersonVerb,
+    TransitiveFirstPersonVerb,
+    TransitiveThirdPersonVerb,
+    Adjective,
+}
+
+impl Category {
+    pub const ALL: [Self; 12] = [
+        Self::Noun,
+        Self::SingularNoun,
+        Self::PluralNoun,
+        Self::MassNoun,
+        Self::SingularOrMassNoun,
+        Self::PluralOrMassNoun,
+        Self::FirstPersonVerb,
+        Self::ThirdPersonVerb,
+        Self::IntransitiveFirstPersonVerb,
+        Self::TransitiveFirstPersonVerb,
+        Self::TransitiveThirdPersonVerb,
+        Self::Adjective,
+    ];
+
+    /// The name used for this category in template slots.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Noun => "noun",
+            Self::SingularNoun => "singular-noun",
+            Self::PluralNoun => "plural-noun",
+            Self::MassNoun => "mass-noun",
+            Self::SingularOrMassNoun => "singular-or-mass-noun",
+            Self::PluralOrMassNoun => "plural-or-mass-noun",
+            Self::FirstPersonVerb => "first-person-verb",
+            Self::ThirdPersonVerb => "third-person-verb",
+            Self::IntransitiveFirstPersonVerb => "intransitive-first-person-verb",
+            Self::TransitiveFirstPersonVerb => "transitive-first-person-verb",
+            Self::TransitiveThirdPersonVerb => "transitive-third-person-verb",
+            Self::Adjective => "adjective",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|category| category.name() == name)
+    }
+
+    /// Whether a lexeme with `flags` can fill a slot of this category.
+    pub fn matches(self, flags: Flags) -> bool {
+        let all = |required: Flags| flags & required == required;
+        let any = |options: Flags| flags & options != 0;
+
+        match self {
+            Self::Noun => any(SINGULAR_NOUN | PLURAL_NOUN | MASS_NOUN),
+            Self::SingularNoun => all(SINGULAR_NOUN),
+            Self::PluralNoun => all(PLURAL_NOUN),
+            Self::MassNoun => all(MASS_NOUN),
+            Self::SingularOrMassNoun => any(SINGULAR_NOUN | MASS_NOUN),
+            Self::PluralOrMassNoun => any(PLURAL_NOUN | MASS_NOUN),
+            Self::FirstPersonVerb => all(FIRST_PERSON_SINGULAR_VERB),
+            Self::ThirdPersonVerb => all(THIRD_PERSON_SINGULAR_VERB),
+            Self::IntransitiveFirstPersonVerb => all(INTRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB),
+            Self::TransitiveFirstPersonVerb => all(TRANSITIVE_VERB | FIRST_PERSON_SINGULAR_VERB),
+            Self::TransitiveThirdPersonVerb => all(TRANSITIVE_VERB | THIRD_PERSON_SINGULAR_VERB),
+            Self::Adjective => (ADJECTIVE_ORDER_QUANTITY..=ADJECTIVE_ORDER_MATERIAL)
+                .contains(&flag_display::adjective_order_category(flags)),
+        }
+    }
+
+    /// What a lexeme needs to fill a slot of this category.
+    pub fn requirement(self) -> &'static str {
+        match self {
+            Self::Noun => "SINGULAR_NOUN, PLURAL_NOUN or MASS_NOUN",
+            Self::SingularNoun => "SINGULAR_NOUN",
+            Self::PluralNoun => "PLURAL_NOUN",
+            Self::MassNoun => "MASS_NOUN",
+            Self::SingularOrMassNoun => "SINGULAR_NOUN or MASS_NOUN",
+            Self::PluralOrMassNoun => "PLURAL_NOUN or MASS_NOUN",
+            Self::FirstPersonVerb => "FIRST_PERSON_SINGULAR_VERB",
+            Self::ThirdPersonVerb => "THIRD_PERSON_SINGULAR_VERB",
+            Self::IntransitiveFirstPersonVerb => "INTRANSITIVE_VERB and FIRST_PERSON_SINGULAR_VERB",
+            Self::TransitiveFirstPersonVerb => "TRANSITIVE_VERB and FIRST_PERSON_SINGULAR_VERB",
+            Self::TransitiveThirdPersonVerb => "TRANSITIVE_VERB and THIRD_PERSON_SINGULAR_VERB",
+            Self::Adjective => "an adjective order category from QUANTITY to MATERIAL",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub category: Category,
+    /// How many lexemes fill the slot. Only adjective slots can have
+    /// more than one, and they are written in adjective order, with each
+    /// from a different adjective order category, like "big red".
+    pub count: usize,
+    /// Whether adjectives from the same adjective order category may
+    /// fill the slot together, as coordinate adjectives. They are
+    /// separated by commas, like "clever, kind old".
+    pub coordinate: bool,
+    /// Slots with the same name are filled with the same lexeme.
+    pub same: Option<Box<str>>,
+    /// Slots in the same distinct group are filled with different
+    /// lexemes, apart from slots that share a name.
+    pub distinct: Option<Box<str>>,
+    /// Adjective slots in the same kin group are filled with adjectives
+    /// from the same adjective order category.
+    pub kin: Option<Box<str>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Part {
+    Text(Box<str>),
+    Slot(Slot),
+    /// "a" or "an", whichever suits the word that follows.
+    Article,
+}
+
+/// What article slots are written as, in place of a category name.
+const ARTICLE_SLOT: &str = "a";
+/// Goes between a slot's category and how many lexemes fill it.
+const COUNT_MARKER: char = '*';
+/// Goes after the count, for slots which allow coordinate adjectives.
+const COORDINATE_MARKER: char = ',';
+/// Goes between a slot's category and its name.
+const SAME_MARKER: char = '=';
+/// Goes between a slot's category, or name, and its distinct group.
+const DISTINCT_MARKER: char = '!';
+/// Goes after everything else in a slot, before its kin group.
+const KIN_MARKER: char = '~';
+
+/// Formats the part in the syntax `parse` accepts.
+impl std::fmt::Display for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Text(text) => write!(f, "{text}"),
+            Self::Slot(Slot{ category, count, coordinate, same, distinct, kin }) => {
+                write!(f, "{{{}", category.name())?;
+                if *count != 1 || *coordinate {
+                    write!(f, "{COUNT_MARKER}{count}")?;
+                }
+                if *coordinate {
+                    write!(f, "{COORDINATE_MARKER}")?;
+                }
+                if let Some(same) = same {
+                    write!(f, "{SAME_MARKER}{same}")?;
+                }
+                if let Some(distinct) = distinct {
+                    write!(f, "{DISTINCT_MARKER}{distinct}")?;
+                }
+                if let Some(kin) = kin {
+                    write!(f, "{KIN_MARKER}{kin}")?;
+                }
+                write!(f, "}}")
+            }
+            Self::Article => write!(f, "{{{ARTICLE_SLOT}}}"),
+        }
+    }
+}
+
+/// Parses the inside of a slot, like "noun", "noun=hero", "noun!a",
+/// "noun=hero!a", "adjective*3", "adjective*3," or "adjective!a~k".
+fn parse_slot(inside: &str) -> Result<Slot, ErrMsg> {
+    let (inside, kin) = match inside.split_once(KIN_MARKER) {
+        Some((rest, kin)) => (rest, Some(kin.trim())),
+        None => (inside, None),
+    };
+    let (rest, distinct) = match inside.split_once(DISTINCT_MARKER) {
+        Some((rest, distinct)) => (rest, Some(distinct.trim())),
+        None => (inside, None),
+    };
+    let (category, same) = match rest.split_once(SAME_MARKER) {
+        Some((category, same)) => (category, Some(same.trim())),
+        None => (rest, None),
+    };
+
+    if same.is_some_and(str::is_empty)
+    || distinct.is_some_and(str::is_empty)
+    || kin.is_some_and(str::is_empty) {
+        return Err("Template has a slot with an empty name, distinct group or kin group")
+    }
+
+    let (category, count) = match category.split_once(COUNT_MARKER) {
+        Some((category, count)) => (category, Some(count.trim())),
+        None => (category, None),
+    };
+    let (count, coordinate) = match count {
+        Some(count) => match count.strip_suffix(COORDINATE_MARKER) {
+            Some(count) => (Some(count.trim()), true),
+            None => (Some(count), false),
+        },
+        None => (None, false),
+    };
+    let count = match count {
+        Some(count) => count.parse().ok()
+            .filter(|&count| count > 0)
+            .ok_or("Template has a slot with a count that is not a positive number")?,
+        None => 1,
+    };
+
+    let category = Category::from_name(category.trim())
+        .ok_or("Template has a slot with an unknown category")?;
+    if (count > 1 || coordinate) && category != Category::Adjective {
+        return Err("Template has a slot with a count, that is not an adjective slot")
+    }
+    if kin.is_some() && (count > 1 || category != Category::Adjective) {
+        return Err("Template has a slot with a kin group, that is not a single adjective slot")
+    }
+
+    Ok(Slot {
+        category,
+        count,
+        coordinate,
+        same: same.map(Box::from),
+        distinct: distinct.map(Box::from),
+        kin: kin.map(Box::from),
+    })
+}
+
+pub fn parse(source: &str) -> Result<Vec<Part>, ErrMsg> {
+    let mut parts = Vec::new();
+
+    let mut rest = source;
+    while let Some(start) = rest.find('{') {
+        if rest[..start].contains('}') {
+            return Err("Template has a } without a matching {")
+        }
+        if start > 0 {
+            parts.push(Part::Text(rest[..start].into()));
+        }
+
+        let after = &rest[start + 1..];
+        let end = after.find('}').ok_or("Template has a { without a matching }")?;
+        let inside = after[..end].trim();
+        if inside == ARTICLE_SLOT {
+            parts.push(Part::Article);
+        } else {
+            parts.push(Part::Slot(parse_slot(inside)?));
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    if rest.contains('}') {
+        return Err("Template has a } without a matching {")
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest.into()));
+    }
+
+    let slots: Vec<&Slot> = slots(&parts).collect();
+    for (i, a) in slots.iter().enumerate() {
+        for b in &slots[i + 1..] {
+            if a.same.is_some() && a.same == b.same && a != b {
+                return Err(
+                    "Template has slots with the same name, but different categories, \
+                    counts or distinct groups"
+                )
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+pub struct Template {
+    pub name: &'static str,
+    /// Each variant is equally likely to be used, given that it can be.
+    pub variants: Vec<Vec<Part>>,
+}
+
+/// The templates from index.html, with constraints added so that, for
+/// example, "the cow is mightier than the cow" cannot happen.
+const BUILTIN: &[(&str, &[&str])] = &[
+    ("oh-my", &["{noun!n} and {noun!n} and {noun!n}, oh my!"]),
+    ("whatever-your", &["Whatever {third-person-verb} your {noun}."]),
+    ("many-light-work", &["Many {plural-noun} make light work."]),
+    ("considered-harmful", &["{noun} considered harmful."]),
+    ("you-may-not-need", &["You may not need {noun}."]),
+    ("will-for", &["Will {first-person-verb} for {noun}."]),
+    ("i's-the-that", &[
+        "I's the {singular-noun=boy!n} that {transitive-third-person-verb!v} \
+        the {singular-noun!n}.\n\
+        And I's the {singular-noun=boy!n} that {transitive-third-person-verb!v} her."
+    ]),
+    ("repeat", &[
+        "{intransitive-first-person-verb!v}. {intransitive-first-person-verb!v}. Repeat."
+    ]),
+    ("why-buy-when-is-free", &[
+        "Why buy the {noun!n} when the {singular-or-mass-noun!n} is free?"
+    ]),
+    ("let-lie", &["Let {adjective} {plural-or-mass-noun} lie."]),
+    ("no-goes-unpunished", &["No {adjective} {singular-or-mass-noun} goes unpunished."]),
+    ("the-the-and-the", &["The {adjective!a}, the {adjective!a}, and the {adjective!a}."]),
+    ("the-catches-the", &[
+        "The {adjective} {singular-or-mass-noun!n} catches the {noun!n}."
+    ]),
+    ("the-is-mightier-than-the", &[
+        "The {singular-or-mass-noun!n} is mightier than the {singular-or-mass-noun!n}."
+    ]),
+    ("you-cant-your-and-it-to", &[
+        "You can't {transitive-first-person-verb!v} your {singular-or-mass-noun} and \
+        {transitive-first-person-verb!v} it too."
+    ]),
+    ("is-the-new", &[
+        "{singular-or-mass-noun!n} is the new {singular-or-mass-noun!n}.",
+        "{adjective!a~k} is the new {adjective!a~k}.",
+    ]),
+    ("the-aint-what-it-used-to-be", &[
+        "The {adjective*2} {singular-or-mass-noun} ain't what it used to be."
+    ]),
+    ("dont-your-before-they", &[
+        "Don't {first-person-verb!v} your {plural-noun} before they {first-person-verb!v}."
+    ]),
+    ("the-and-the", &[
+        "The {adjective*2!a} {plural-noun!n} and the {adjective*2!a} \
+        {singular-or-mass-noun!n}"
+    ]),
+    ("hey-and-a-first-buddy", &["Hey, {mass-noun} and {a} {singular-noun}, first, buddy!"]),
+];
+
+pub fn builtin() -> Result<Vec<Template>, ErrMsg> {
+    BUILTIN.iter()
+        .map(|&(name, sources)| Ok(Template {
+            name,
+            variants: sources.iter()
+                .map(|source| parse(source))
+                .collect::<Result<_, _>>()?,
+        }))
+        .collect()
+}
+
+/// The number of lexemes in `lll` that could fill a slot of `category`.
+pub fn candidate_count(lll: &[LLRef<'_>], category: Category) -> usize {
+    lll.iter().filter(|ll| category.matches(ll.flags)).count()
+}
+
+/// A reason a variant of a template cannot be filled in.
+pub enum Problem {
+    /// No lexeme could fill a slot of this category.
+    Empty(Category),
+    /// The slots in a distinct group need more different lexemes than
+    /// there are. `category` is `None` if the problem is with the group
+    /// as a whole, rather than just the slots of one category.
+    TooFew{
+        group: Box<str>,
+        category: Option<Category>,
+        needed: usize,
+        available: usize,
+    },
+    /// An adjective slot needs more adjectives than the lll has, or, if
+    /// they cannot be coordinate, more adjective order categories.
+    TooFewAdjectives{
+        count: usize,
+        coordinate: bool,
+        available: usize,
+    },
+    /// The slots in a kin group need more different adjectives than any
+    /// one adjective order category has.
+    TooFewKin{
+        group: Box<str>,
+        needed: usize,
+        available: usize,
+    },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty(category) => write!(
+                f,
+                "{} has no candidates. Add a lexeme with {}.",
+                category.name(),
+                category.requirement(),
+            ),
+            Self::TooFew{ group, category, needed, available } => {
+                write!(f, "The {DISTINCT_MARKER}{group} slots need {needed} different ")?;
+                if let Some(category) = category {
+                    write!(f, "{} ", category.name())?;
+                }
+                write!(f, "lexemes, but the lll only has {available}.")?;
+                if let Some(category) = category {
+                    write!(f, " Add more with {}.", category.requirement())?;
+                }
+                Ok(())
+            }
+            Self::TooFewAdjectives{ count, coordinate: true, available } => write!(
+                f,
+                "A slot needs {count} different adjectives, but the lll only has {available}.",
+            ),
+            Self::TooFewAdjectives{ count, coordinate: false, available } => write!(
+                f,
+                "A slot needs adjectives from {count} different adjective order categories, \
+                but the lll only has adjectives from {available}. Add adjectives from other \
+                categories, or let the slot take coordinate adjectives, by putting \
+                {COORDINATE_MARKER} after its count.",
+            ),
+            Self::TooFewKin{ group, needed, available } => write!(
+                f,
+                "The {KIN_MARKER}{group} slots need {needed} different adjectives from one \
+                adjective order category, but the lll has at most {available} in any one. \
+                Add more adjectives to a category.",
+            ),
+        }
+    }
+}
+
+/// How many different adjective order categories the adjectives in
+/// `lll` are from.
+pub fn adjective_order_category_count(lll: &[LLRef<'_>]) -> usize {
+    let mut categories: Vec<AdjectiveOrderCategory> = lll.iter()
+        .filter(|ll| Category::Adjective.matches(ll.flags))
+        .map(|ll| flag_display::adjective_order_category(ll.flags))
+        .collect();
+    categories.sort_unstable();
+    categories.dedup();
+    categories.len()
+}
+
+fn slots(parts: &[Part]) -> impl Iterator<Item = &Slot> {
+    parts.iter().filter_map(|part| match part {
+        Part::Slot(slot) => Some(slot),
+        _ => None,
+    })
+}
+
+/// How many different adjectives the slots in the kin group `kin` need,
+/// which is more than one if some of them are in the same distinct group.
+fn kin_needs(parts: &[Part], kin: &str) -> usize {
+    let kin_slots: Vec<&Slot> = slots(parts)
+        .filter(|slot| slot.kin.as_deref() == Some(kin))
+        .collect();
+
+    kin_slots.iter()
+        .filter_map(|slot| slot.distinct.as_deref())
+        .map(|group| {
+            let mut names = Vec::new();
+            kin_slots.iter()
+                .filter(|slot| slot.distinct.as_deref() == Some(group))
+                .filter(|slot| match slot.same.as_deref() {
+                    Some(same) if names.contains(&same) => false,
+                    Some(same) => {
+                        names.push(same);
+                        true
+                    }
+                    None => true,
+                })
+                .count()
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// The number of adjectives in `lll` from each adjective order category
+/// that has any.
+fn adjectives_per_order_category(lll: &[LLRef<'_>]) -> Vec<(AdjectiveOrderCategory, usize)> {
+    let mut counts: Vec<(AdjectiveOrderCategory, usize)> = Vec::new();
+    for ll in lll.iter().filter(|ll| Category::Adjective.matches(ll.flags)) {
+        let category = flag_display::adjective_order_category(ll.flags);
+        match counts.iter_mut().find(|(c, _)| *c == category) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((category, 1)),
+        }
+    }
+    counts
+}
+
+/// The adjective order categories in `lll` with enough adjectives to fill
+/// the slots in the kin group `kin`, in the variant made of `parts`.
+pub fn kin_order_categories(
+    lll: &[LLRef<'_>],
+    parts: &[Part],
+    kin: &str,
+) -> Vec<AdjectiveOrderCategory> {
+    let needed = kin_needs(parts, kin);
+    adjectives_per_order_category(lll).into_iter()
+        .filter(|&(_, count)| count >= needed)
+        .map(|(category, _)| category)
+        .collect()
+}
+
+/// The reasons the variant made of `parts` cannot be filled in from
+/// `lll`. Empty if it can be.
+pub fn problems(lll: &[LLRef<'_>], parts: &[Part]) -> Vec<Problem> {
+    let slots: Vec<&Slot> = slots(parts).collect();
+
+    let mut problems = Vec::new();
+
+    for slot in &slots {
+        let category = slot.category;
+        let already = problems.iter().any(|problem| matches!(
+            problem,
+            Problem::Empty(c) if *c == category
+        ));
+        if !already && candidate_count(lll, category) == 0 {
+            problems.push(Problem::Empty(category));
+        }
+    }
+    if !problems.is_empty() {
+        // Anything else would only be restating these.
+        return problems
+    }
+
+    for slot in &slots {
+        if slot.count == 1 {
+            continue
+        }
+        let available = if slot.coordinate {
+            candidate_count(lll, slot.category)
+        } else {
+            adjective_order_category_count(lll)
+        };
+        let already = problems.iter().any(|problem| matches!(
+            problem,
+            Problem::TooFewAdjectives{ count, coordinate, .. }
+                if *count == slot.count && *coordinate == slot.coordinate
+        ));
+        if !already && slot.count > available {
+            problems.push(Problem::TooFewAdjectives{
+                count: slot.count,
+                coordinate: slot.coordinate,
+                available,
+            });
+        }
+    }
+
+    let mut kins: Vec<&str> = slots.iter()
+        .filter_map(|slot| slot.kin.as_deref())
+        .collect();
+    kins.sort_unstable();
+    kins.dedup();
+
+    for kin in kins {
+        if kin_order_categories(lll, parts, kin).is_empty() {
+            problems.push(Problem::TooFewKin{
+                group: kin.into(),
+                needed: kin_needs(parts, kin),
+                available: adjectives_per_order_category(lll).into_iter()
+                    .map(|(_, count)| count)
+                    .max()
+                    .unwrap_or(0),
+            });
+        }
+    }
+
+    let mut groups: Vec<&str> = slots.iter()
+        .filter_map(|slot| slot.distinct.as_deref())
+        .collect();
+    groups.sort_unstable();
+    groups.dedup();
+
+    for group in groups {
+        // Slots sharing a name take one lexeme between them.
+        let mut seen_names = Vec::new();
+        let mut needed: Vec<(Category, usize)> = Vec::new();
+        for slot in &slots {
+            if slot.distinct.as_deref() != Some(group) {
+                continue
+            }
+            if let Some(same) = &slot.same {
+                if seen_names.contains(&same) {
+                    continue
+                }
+                seen_names.push(same);
+            }
+
+            let category = slot.category;
+            match needed.iter_mut().find(|(c, _)| *c == category) {
+                Some((_, count)) => *count += slot.count,
+                None => needed.push((category, slot.count)),
+            }
+        }
+
+        let mut too_few = false;
+        for &(category, count) in &needed {
+            let available = candidate_count(lll, category);
+            if count > available {
+                too_few = true;
+                problems.push(Problem::TooFew{
+                    group: group.into(),
+                    category: Some(category),
+                    needed: count,
+                    available,
+                });
+            }
+        }
+
+        if !too_few {
+            // The categories might overlap, like noun and singular-noun,
+            // so there being enough of each is not enough on its own.
+            let total = needed.iter().map(|(_, count)| count).sum();
+            let available = lll.iter()
+                .filter(|ll| needed.iter().any(|(c, _)| c.matches(ll.flags)))
+                .count();
+            if total > available {
+                problems.push(Problem::TooFew{
+                    group: group.into(),
+                    category: None,
+                    needed: total,
+                    available,
+                });
+            }
+        }
+    }
+
+    problems
+}
+
+struct TemplateCheck<'templates> {
+    template: &'templates Template,
+    /// The problems with each variant.
+    variants: Vec<Vec<Problem>>,
+}
+
+impl TemplateCheck<'_> {
+    fn is_usable(&self) -> bool {
+        self.variants.iter().any(Vec::is_empty)
+    }
+}
+
+/// Which templates can be filled in from an lll, and what is missing for
+/// the ones that cannot be.
+pub struct Report<'templates> {
+    templates: Vec<TemplateCheck<'templates>>,
+}
+
+impl Report<'_> {
+    pub fn all_usable(&self) -> bool {
+        self.templates.iter().all(TemplateCheck::is_usable)
+    }
+}
+
+pub fn check<'templates>(
+    lll: &[LLRef<'_>],
+    templates: &'templates [Template],
+) -> Report<'templates> {
+    let templates = templates.iter()
+        .map(|template| TemplateCheck {
+            template,
+            variants: template.variants.iter()
+                .map(|parts| problems(lll, parts))
+                .collect(),
+        })
+        .collect();
+
+    Report{ templates }
+}
+
+impl std::fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut usable_count = 0;
+
+        for check in &self.templates {
+            let usable = check.is_usable();
+            if usable {
+                usable_count += 1;
+            }
+            writeln!(
+                f,
+                "{}: {}",
+                check.template.name,
+                if usable { "usable" } else { "UNUSABLE" }
+            )?;
+
+            let variant_count = check.variants.len();
+            for (i, problems) in check.variants.iter().enumerate() {
+                if problems.is_empty() {
+                    continue
+                }
+
+                let indent = if variant_count > 1 {
+                    write!(f, "  variant {} of {variant_count} is unusable: \"", i + 1)?;
+                    for part in &check.template.variants[i] {
+                        write!(f, "{part}")?;
+                    }
+                    writeln!(f, "\"")?;
+                    "    "
+                } else {
+                    "  "
+                };
+
+                for problem in problems {
+                    writeln!(f, "{indent}{problem}")?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{usable_count} of {} templates usable", self.templates.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems_with(lll: &[LL], source: &str) -> Vec<Problem> {
+        let bytes = encode_lll(lll);
+        let lll = parse_lll_refs(&bytes).unwrap();
+        problems(&lll, &parse(source).unwrap())
+    }
+
+    #[test]
+    fn parse_reads_slots_and_text() {
+        let parts = parse("The {adjective*2,=x!a} {noun}, {a} {noun=y!a}.").unwrap();
+        let text: String = parts.iter().map(ToString::to_string).collect();
+        assert_eq!(text, "The {adjective*2,=x!a} {noun}, {a} {noun=y!a}.");
+        assert!(matches!(
+            &parts[1],
+            Part::Slot(Slot{ category: Category::Adjective, count: 2, coordinate: true, .. })
+        ));
+        assert!(matches!(parts[5], Part::Article));
+    }
+
+    #[test]
+    fn parse_rejects_slots_that_share_a_name_but_not_the_rest() {
+        let mismatch = Err(
+            "Template has slots with the same name, but different categories, \
+            counts or distinct groups"
+        );
+        assert_eq!(parse("{noun=x} {singular-noun=x}").map(|_| ()), mismatch);
+        assert_eq!(parse("{noun=x!a} {noun=x!b}").map(|_| ()), mismatch);
+        assert_eq!(parse("{adjective*2=x} {adjective=x}").map(|_| ()), mismatch);
+        assert!(parse("{noun=x!a} {noun=x!a}").is_ok());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_slots() {
+        assert!(parse("{noun").is_err());
+        assert!(parse("noun}").is_err());
+        assert!(parse("{verb}").is_err());
+        assert!(parse("{noun*2}").is_err());
+        assert!(parse("{adjective*0}").is_err());
+        assert!(parse("{noun=}").is_err());
+    }
+
+    #[test]
+    fn problems_is_empty_when_the_lll_has_what_is_needed() {
+        let lll = [ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN)];
+        assert!(problems_with(&lll, "{noun!n} and {singular-noun!n}").is_empty());
+    }
+
+    #[test]
+    fn problems_reports_empty_categories() {
+        let problems = problems_with(&[ll("cow", SINGULAR_NOUN)], "{plural-noun} {plural-noun}");
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0], Problem::Empty(Category::PluralNoun)));
+    }
+
+    #[test]
+    fn problems_reports_too_few_for_a_distinct_group() {
+        let lll = [ll("cow", SINGULAR_NOUN), ll("hat", SINGULAR_NOUN)];
+        let problems = problems_with(&lll, "{noun!n}, {noun!n} and {noun=x!n} {noun=x!n}");
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            &problems[0],
+            Problem::TooFew{ group, category: Some(Category::Noun), needed: 3, available: 2 }
+                if &**group == "n"
+        ));
+    }
+
+    #[test]
+    fn problems_totals_overlapping_categories_in_a_distinct_group() {
+        // There is a noun and a singular noun, but they are the same one.
+        let lll = [ll("cow", SINGULAR_NOUN), ll("moo", INTRANSITIVE_VERB)];
+        let problems = problems_with(&lll, "{noun!n} and {singular-noun!n}");
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            &problems[0],
+            Problem::TooFew{ group, category: None, needed: 2, available: 1 } if &**group == "n"
+        ));
+    }
+
+    #[test]
+    fn parse_only_allows_kin_groups_on_single_adjective_slots() {
+        assert!(parse("{adjective!a~k} {adjective~k}").is_ok());
+        assert!(parse("{noun~k}").is_err());
+        assert!(parse("{adjective*2~k}").is_err());
+        assert!(parse("{adjective~}").is_err());
+    }
+
+    #[test]
+    fn problems_reports_kin_groups_with_too_few_adjectives_in_any_category() {
+        let lll = [adjective("red", 10), adjective("old", 9), adjective("big", 6)];
+        let problems = problems_with(&lll, "{adjective!a~k} is the new {adjective!a~k}.");
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            &problems[0],
+            Problem::TooFewKin{ group, needed: 2, available: 1 } if &**group == "k"
+        ));
+
+        // Without a distinct group, the same adjective can fill both.
+        assert!(problems_with(&lll, "{adjective~k} is the new {adjective~k}.").is_empty());
+
+        let lll = [adjective("red", 10), adjective("blue", 10), adjective("old", 9)];
+        assert!(problems_with(&lll, "{adjective!a~k} is the new {adjective!a~k}.").is_empty());
+    }
+
+    #[test]
+    fn problems_reports_too_few_adjective_order_categories() {
+        let lll = [adjective("big", 6), adjective("small", 6), adjective("red", 10)];
+        let problems = problems_with(&lll, "{adjective*3} {adjective*2,}");
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            problems[0],
+            Problem::TooFewAdjectives{ count: 3, coordinate: false, available: 2 }
+        ));
+        assert!(problems_with(&lll, "{adjective*3,}").is_empty());
+    }
+}